@@ -3,16 +3,22 @@ mod font;
 mod road;
 mod image_set;
 mod model;
+mod version;
+
+pub use version::{GameVersion, Profile};
 
 use {
     std::collections::HashMap,
     anyhow::{Result as Anyhow, Context as _},
     camino::{Utf8Path as Path, Utf8PathBuf as PathBuf},
+    formats::{prm, tiles, reader::{self, Endian, InFile as _}},
 };
 
 pub struct Config {
     pub wipeout_dir: PathBuf,
     pub out_path:    PathBuf,
+    /// Which release `wipeout_dir` holds data from
+    pub version:     GameVersion,
 }
 
 pub fn make_bundle(config: Config) -> Anyhow<()> {
@@ -56,7 +62,7 @@ fn make_tracks(bundler: &mut Bundler, tracks_dir: &Path)
         let entry = entry?;
         if !entry.file_type()?.is_dir() {continue}
         let entry_name = entry.file_name();
-        if !entry_name.starts_with("track") {continue}
+        if !entry_name.starts_with(bundler.profile.track_prefix) {continue}
         let track = make_track(bundler, entry_name.into())
             .with_context(|| format!("track: '{entry_name}'"))?;
         tracks.insert(entry_name.into(), track);
@@ -69,17 +75,20 @@ fn make_track(bundler: &mut Bundler, track_name: &Path) -> Anyhow<crate::Track>
     let scenery_scene = bundler.scene(&track_name.join("scene.prm"))?;
     let scenery_iset = bundler.image_set(&track_name.join("scene.cmp"), None)?;
 
-    let (road_model, graph, road_iset) = {
+    let (road_model, graph, visibility, road_iset) = {
         let iset = bundler.image_set(
             &track_name.join("library.cmp"),
-            Some(&track_name.join("library.ttf"))
+            Some(&track_name.join("library.ttf")),
         )?;
 
-        let trv = bundler.load(track_name.join("track.trv"))?;
-        let trf = bundler.load(track_name.join("track.trf"))?;
-        let trs = bundler.load(track_name.join("track.trs"))?;
-        let (model, graph) = road::make_road(&trv, &trf, &trs)?;
-        (model, graph, iset)
+        use formats::track;
+        let verts    = bundler.parse(track_name.join("track.trv"), track::load_trv)?;
+        let faces    = bundler.parse(track_name.join("track.trf"), track::load_trf)?;
+        let sections = bundler.parse(track_name.join("track.trs"), track::load_trs)?;
+        let vew      = bundler.parse_optional(track_name.join("track.vew"), track::load_vew)?;
+        if vew.is_none() {log::warn!("{track_name} has no track.vew; nothing will be culled")}
+        let (model, graph, vis) = road::make_road(&verts, &faces, &sections, vew.as_deref())?;
+        (model, graph, vis, iset)
     };
 
     Ok(crate::Track {
@@ -87,6 +96,7 @@ fn make_track(bundler: &mut Bundler, track_name: &Path) -> Anyhow<crate::Track>
         scenery_scene, scenery_iset,
         sky_mset, sky_iset,
         graph,
+        visibility,
     })
 }
 
@@ -124,13 +134,15 @@ fn make_sky(bundler: &mut Bundler, track_name: &Path)
 
 fn make_ships(bundler: &mut Bundler) -> Anyhow<(crate::ModelSet, crate::ImageSet)> {
     log::info!("making ships");
-    let iset = bundler.image_set("common/allsh.cmp".into(), None)?;
-    let mset = bundler.model_set("common/allsh.prm".into())?;
+    let ships = Path::new(bundler.profile.ships);
+    let iset = bundler.image_set(&ships.with_extension("cmp"), None)?;
+    let mset = bundler.model_set(&ships.with_extension("prm"))?;
     Ok((mset, iset))
 }
 
 struct Bundler {
     config: Config,
+    profile: Profile,
     aux_file: std::io::BufWriter<std::fs::File>,
     aux_tab: HashMap<String, u64>,
 }
@@ -144,7 +156,9 @@ impl Bundler {
                 .with_context(|| format!("aux_path: {aux_path}"))?
         );
         let aux_tab = HashMap::new();
-        Ok(Bundler{config, aux_file, aux_tab})
+        let profile = config.version.profile();
+        log::info!("bundling {:?} data", config.version);
+        Ok(Bundler{config, profile, aux_file, aux_tab})
     }
 
     fn asset_dir(&self, rel: &Path) -> PathBuf {
@@ -169,12 +183,47 @@ impl Bundler {
         Ok(bytes)
     }
 
+    /// Loads a file that some data sets lack, like a track's `.vew`.
+    fn load_optional(&self, path: impl AsRef<Path>) -> Anyhow<Option<Vec<u8>>> {
+        match self.load(&path) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.downcast_ref::<std::io::Error>()
+                .is_some_and(|e| e.kind() == std::io::ErrorKind::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Loads and parses a file in the profile's byte order, naming it in any parse error.
+    fn parse<T>(
+        &self,
+        path: impl AsRef<Path>,
+        f: impl FnOnce(&[u8], Endian) -> Result<T, reader::Error>,
+    ) -> Anyhow<T> {
+        let path = path.as_ref();
+        let bytes = self.load(path)?;
+        Ok(f(&bytes, self.profile.endian).in_file(path.as_str())?)
+    }
+
+    /// Like `parse`, for a file that some data sets lack.
+    fn parse_optional<T>(
+        &self,
+        path: impl AsRef<Path>,
+        f: impl FnOnce(&[u8], Endian) -> Result<T, reader::Error>,
+    ) -> Anyhow<Option<T>> {
+        let path = path.as_ref();
+        let Some(bytes) = self.load_optional(path)? else {return Ok(None)};
+        Ok(Some(f(&bytes, self.profile.endian).in_file(path.as_str())?))
+    }
+
     // TODO dedup
-    fn image_set(&mut self, cmp_path: &Path, ttf_path: Option<&Path>) -> Anyhow<crate::ImageSet> {
+    fn image_set(&mut self, cmp_path: &Path, ttf_path: Option<&Path>)
+        -> Anyhow<crate::ImageSet>
+    {
         let cmp = self.load(cmp_path)?;
-        let ttf = ttf_path.map(|p| self.load(p)).transpose()?;
+        let maps = ttf_path.map(|p| self.parse(p, tiles::load_ttf)).transpose()?;
         let label = cmp_path.as_str().replace("/", "_");
-        image_set::build(&label, &cmp, ttf.as_deref())
+        image_set::build(&label, &cmp, maps)
+            .with_context(|| cmp_path.to_string())
     }
 
     /*fn atlas(&mut self, cmp_path: &Path)
@@ -197,15 +246,13 @@ impl Bundler {
     }*/
 
     fn scene(&mut self, prm_path: &Path) -> Anyhow<crate::Scene> {
-        let prm = self.load(prm_path)?;
-        //let debug_label = prm_path.as_str();
-        model::build_scene(&prm)
+        let objects = self.parse(prm_path, prm::load_prm)?;
+        model::build_scene(&objects).with_context(|| prm_path.to_string())
     }
 
     fn model_set(&mut self, prm_path: &Path) -> Anyhow<crate::ModelSet> {
-        let prm = self.load(prm_path)?;
-        //let debug_label = prm_path.as_str();
-        model::build(&prm)
+        let objects = self.parse(prm_path, prm::load_prm)?;
+        model::build(&objects).with_context(|| prm_path.to_string())
     }
 
     fn aux_blob(&mut self, name: &str, path: &Path) -> Anyhow<()> {
@@ -1,38 +1,48 @@
 use {
-    crate::be::*,
-    anyhow::Result as Anyhow,
+    anyhow::{Result as Anyhow, anyhow},
     bytemuck as bm,
+    formats::tiles,
     pixmap::{Pixmap, Rgba},
     //rapid_qoi::Qoi,
 };
 
-pub fn build(label: &str, cmp: &[u8], ttf: Option<&[u8]>) -> Anyhow<crate::ImageSet> {
-    let images = formats::load_cmp(&cmp)?;
+/// Builds an image set from the textures in `cmp`. Given tile maps, the textures are fragments,
+/// and the set holds the tiles assembled from them instead; every high-detail tile, then every
+/// medium, then every low.
+pub fn build(label: &str, cmp: &[u8], maps: Option<Vec<tiles::TileMap>>)
+    -> Anyhow<crate::ImageSet>
+{
+    let textures = formats::load_cmp(&cmp)?;
 
-    let images = if let Some(ttf) = ttf {
-        let frag_dim = images[0].wide();
-        bm::cast_slice(ttf)
-            .iter()
-            .map(|fm: &FragMap| {
-                let mut image = Pixmap::new(frag_dim*4, frag_dim*4, Rgba::TRANSPARENT);
-                for (fi, fj) in util::row_major(0..4, 0..4) {
-                    let fx = fi * frag_dim;
-                    let fy = fj * frag_dim;
-                    let frag = fm.hi[fj as usize][fi as usize].get() as usize;
-                    let src = &images[frag];
-                    image.copy_from([fx as i32, fy as i32], &src);
-                }
-                Ok(image)
-            })
-            .collect::<Anyhow<Vec<_>>>()?
+    let (textures, lods) = if let Some(maps) = maps {
+        let hi = maps.iter().enumerate().map(|(ti, map)| compose(ti, &map.hi, &textures));
+        let md = maps.iter().enumerate().map(|(ti, map)| compose(ti, &map.md, &textures));
+        let lo = maps.iter().enumerate().map(|(ti, map)| compose(ti, &map.lo, &textures));
+        let tiles = hi.chain(md).chain(lo).collect::<Anyhow<Vec<_>>>()?;
+        (tiles, 3)
     }
     else {
-        images
+        (textures, 1)
     };
 
+    let stp = pack_bits(textures.iter().flat_map(|tex| tex.stp.iter().copied()));
+
+    // STP texels go in with alpha 0x80, which is how `qoit::psx` spells STP, so that black
+    // `0x8000` texels can still take the PSX coding.
+    let images = textures.into_iter()
+        .map(|tex| {
+            let wide = tex.image.wide();
+            Pixmap::new_from_fn(wide, tex.image.high(), |[x, y]| {
+                let Rgba([r, g, b, a]) = tex.image.get([x, y]).unwrap();
+                let stp = tex.stp[(y * wide + x) as usize];
+                Rgba([r, g, b, if stp && a != 0 {0x80} else {a}])
+            })
+        })
+        .collect::<Vec<_>>();
+
     for (i, image) in images.iter().enumerate() {
-        image::save_buffer(
-            format!("debug-out/iset-{label}-{i}.tga"),
+        qoit::codec::save_buffer(
+            format!("debug-out/iset-{label}-{i}.qoi"),
             bytemuck::cast_slice(image.try_as_slice().unwrap()),
             image.wide() as u32,
             image.high() as u32,
@@ -44,22 +54,70 @@ pub fn build(label: &str, cmp: &[u8], ttf: Option<&[u8]>) -> Anyhow<crate::Image
         .map(|img| (img.wide().try_into().unwrap(), img.high().try_into().unwrap()))
         .collect();
 
+    // Textures are PSX colour unless they were 24-bit, so they can usually take the PSX
+    // coding, which comes out smaller.
+    let is_psx = |image: &Pixmap<_>| {
+        let pixels: &[qoit::Pixel] = bm::cast_slice(image.try_as_slice().unwrap());
+        pixels.iter().all(|&pixel| qoit::psx::to_word(pixel).is_some())
+    };
+    let coding = if images.iter().all(is_psx) {
+        crate::ImageCoding::QoiPsx
+    }
+    else {
+        crate::ImageCoding::Qoi
+    };
+
     let mut qoi_stream = Vec::with_capacity(0x10_0000);
-    let mut state = qoit::State::new();
+    let mut offsets = Vec::with_capacity(images.len());
     for image in images {
-        state.encode_some(&mut qoi_stream, bm::cast_slice(image.try_as_slice().unwrap()))
-            .unwrap();
+        offsets.push(qoi_stream.len().try_into()?);
+        let pixels = bm::cast_slice(image.try_as_slice().unwrap());
+        match coding {
+            crate::ImageCoding::Qoi => {
+                let mut qoi = qoit::QoiWriter::headerless(qoi_stream);
+                qoi.write_pixels(pixels)?;
+                qoi_stream = qoi.finish()?;
+            }
+            crate::ImageCoding::QoiPsx => {
+                let mut state = qoit::psx::State::new();
+                state.encode_some(&mut qoi_stream, pixels)?;
+                state.encode_flush(&mut qoi_stream)?;
+            }
+        }
     }
-    state.encode_flush(&mut qoi_stream).unwrap();
-    //qoi_stream.extend_from_slice(&[0,0,0,0,0,0,0,1]);
 
-    Ok(crate::ImageSet{sizes, qoi_stream})
+    Ok(crate::ImageSet{sizes, coding, qoi_stream, offsets, stp, lods})
 }
 
-#[derive(Clone, Copy, bm::AnyBitPattern)]
-struct FragMap {
-    hi:  [[Be<u16>; 4]; 4],
-    _md: [[Be<u16>; 2]; 2],
-    _lo: [[Be<u16>; 1]; 1],
+/// Packs `bits` eight to a byte, low bit first.
+fn pack_bits(bits: impl Iterator<Item = bool>) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for (i, bit) in bits.enumerate() {
+        if i % 8 == 0 {bytes.push(0)}
+        *bytes.last_mut().unwrap() |= (bit as u8) << (i % 8);
+    }
+    bytes
 }
 
+/// Assembles one tile from an `N`×`N` grid of fragments.
+fn compose<const N: usize>(ti: usize, map: &[[u16; N]; N], frags: &[formats::Texture])
+    -> Anyhow<formats::Texture>
+{
+    let frag_dim = frags[0].image.wide();
+    let tile_dim = frag_dim * N as i32;
+    let mut image = Pixmap::new(tile_dim, tile_dim, Rgba::TRANSPARENT);
+    let mut stp = vec![false; (tile_dim * tile_dim) as usize];
+    for (fi, fj) in util::row_major(0..N as i32, 0..N as i32) {
+        let fx = fi * frag_dim;
+        let fy = fj * frag_dim;
+        let frag = map[fj as usize][fi as usize] as usize;
+        let src = frags.get(frag)
+            .ok_or_else(|| anyhow!("tile {ti} uses missing fragment {frag}"))?;
+        image.copy_from([fx, fy], &src.image);
+        for (sx, sy) in util::row_major(0..frag_dim, 0..frag_dim) {
+            let di = (fy + sy) * tile_dim + fx + sx;
+            stp[di as usize] = src.stp[(sy * frag_dim + sx) as usize];
+        }
+    }
+    Ok(formats::Texture{image, stp})
+}
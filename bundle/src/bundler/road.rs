@@ -1,116 +1,102 @@
 use {
-    crate::be::Be,
-    anyhow::Result as Anyhow,
-    bytemuck as bm,
+    anyhow::{Result as Anyhow, anyhow},
+    formats::track::{Vertex, Face, Section},
     ultraviolet as uv,
 };
 
-pub fn make_road(trv: &[u8], trf: &[u8], trs: &[u8])
-    -> Anyhow<(crate::RoadModel, crate::TrackGraph)>
+pub fn make_road(verts: &[Vertex], faces: &[Face], sections: &[Section], vew: Option<&[i16]>)
+    -> Anyhow<(crate::RoadModel, crate::TrackGraph, crate::Visibility)>
 {
-    let vs = bm::try_cast_slice(trv)?;
-    let fs = bm::try_cast_slice(trf)?;
-    let model = make_model(&vs, &fs)?;
-    let graph = make_graph(&trs, &vs, &fs);
-    Ok((model, graph))
+    let model = make_model(verts, faces)?;
+    let graph = make_graph(sections, verts, faces)?;
+    let vis = vew.map_or_else(Default::default, |vew| make_visibility(sections, vew));
+    Ok((model, graph, vis))
 }
 
-#[repr(transparent)]
-#[derive(Debug, Clone, Copy, bm::AnyBitPattern)]
-struct RawVert([Be<i32>; 4]);
-
-impl From<RawVert> for [f32; 3] {
-    fn from(RawVert([x,y,z,_]): RawVert) -> Self {
-        [x,y,z].map(|x| x.get() as f32)
-    }
-}
-
-impl From<RawVert> for uv::Vec3 {
-    fn from(v: RawVert) -> Self {
-        let xyz: [f32; 3] = v.into();
-        xyz.into()
-    }
-}
-
-#[repr(C)]
-#[derive(Debug, Clone, Copy, bm::AnyBitPattern)]
-struct RawFace {
-    verts:  [Be<u16>; 4],
-    _normal: [Be<i16>; 3],
-    tex:    u8,
-    flags:  u8,
-    colour: super::RawRgbx,
-}
-
-fn make_model(verts: &[RawVert], faces: &[RawFace]) -> Anyhow<crate::RoadModel> {
-    let verts = verts.iter().copied().map(Into::into).collect();
+fn make_model(verts: &[Vertex], faces: &[Face]) -> Anyhow<crate::RoadModel> {
+    let verts = verts.iter()
+        .map(|v| v.xyz.map(|x| x as f32))
+        .collect::<Vec<_>>();
 
     let mut f_verts = Vec::with_capacity(faces.len());
     let mut f_tex   = Vec::with_capacity(faces.len());
     let mut f_flags = Vec::with_capacity(faces.len());
     let mut f_rgb   = Vec::with_capacity(faces.len());
 
-    for face in faces {
-        f_verts.push(face.verts.map(Be::get));
+    for (face_i, face) in faces.iter().enumerate() {
+        if let Some(&vi) = face.verts.iter().find(|&&vi| vi as usize >= verts.len()) {
+            return Err(anyhow!("face {face_i} uses vertex {vi}, but there are {}", verts.len()));
+        }
+        f_verts.push(face.verts);
         f_tex.push(face.tex);
         f_flags.push(face.flags);
-        f_rgb.push(face.colour.into());
+        let [r, g, b] = face.colour;
+        f_rgb.push(super::RawRgbx([r, g, b, 0]).into());
     }
 
     Ok(crate::RoadModel{verts, f_verts, f_tex, f_flags, f_rgb})
 }
 
-#[repr(C)]
-#[derive(Clone, Copy, bm::AnyBitPattern)]
-struct RawSection {
-    junction: Be<u32>,      //    0 ..   4
-    prev:     Be<u32>,      //    4 ..   8
-    next:     Be<u32>,      //    8 ..  12
-    centre:   [Be<i32>; 3], //   12 ..  24
-    _pad0a:   [u8; 32],     //   24 ..
-    _pad0b:   [u8; 32],
-    _pad0c:   [u8; 32],
-    _pad0d:   [u8; 20],     //      .. 140
-    face_i0:  Be<u32>,      //  140 .. 144
-    n_faces:  Be<u16>,      //  144 .. 146
-    _pad1:    [u8; 4],      //  146 .. 150
-    flags:    Be<u16>,      //  150 .. 152
-    _pad3:    [u8; 4],      //  152 .. 156
+/// The `.vew` layout is a guess (see `Section::view_list`), so if any view list can't be read, or
+/// names a section that isn't there, the guess is taken to be wrong and the track gets no
+/// visibility at all, which culls nothing.
+fn make_visibility(sections: &[Section], vew: &[i16]) -> crate::Visibility {
+    let mut vis = crate::Visibility::default();
+    let mut bad = None;
+    for (sect_i, sect) in sections.iter().enumerate() {
+        vis.sect_0.push(vis.sects.len() as u32);
+        let visible = sect.visible_sections(vew, |e| {
+            bad.get_or_insert(e.context(format!("section {sect_i}")));
+        });
+        if let Some(si) = visible.iter().find(|&&si| si as usize >= sections.len()) {
+            bad.get_or_insert(anyhow!("section {sect_i} sees section {si}, but there are {}",
+                sections.len()));
+        }
+        vis.sects.extend(visible);
+    }
+
+    if let Some(e) = bad {
+        log::warn!("not culling by .vew: {e:#}");
+        return crate::Visibility::default();
+    }
+    vis
 }
 
-fn make_graph(trs: &[u8], verts: &[RawVert], faces: &[RawFace]) -> crate::TrackGraph {
-    let raw_sections: &[RawSection] = bm::cast_slice(trs);
-    raw_sections.iter()
-        .map(|rs| {
-            let i0 = rs.face_i0.get() as usize;
-            let nf = rs.n_faces.get() as usize;
+fn make_graph(sections: &[Section], verts: &[Vertex], faces: &[Face])
+    -> Anyhow<crate::TrackGraph>
+{
+    sections.iter()
+        .enumerate()
+        .map(|(sect_i, sect)| {
+            let sect_faces = faces.get(sect.faces())
+                .ok_or(anyhow!("section {sect_i} has faces {:?}, but there are {}",
+                    sect.faces(), faces.len()))?;
 
-            let center = (i0 .. i0+nf)
-                .filter_map(|i| {
-                    let face = &faces[i];
-                    if (face.flags & 1) == 0 {return None}
-                    let p = face.verts.into_iter()
+            let center = sect_faces.iter()
+                .filter(|face| (face.flags & 1) != 0)
+                .map(|face| {
+                    face.verts.into_iter()
                         .map(|vi| {
-                            let p: uv::Vec3 = verts[vi.get() as usize].into();
+                            let p = uv::Vec3::from(verts[vi as usize].xyz.map(|x| x as f32));
                             p.into_homogeneous_point()
                         })
-                        .sum::<uv::Vec4>();
-                    Some(p)
+                        .sum::<uv::Vec4>()
                 })
                 .sum::<uv::Vec4>()
                 .normalized_homogeneous_point()
                 .xyz()
                 .into();
 
-            let prev = rs.prev.get();
-            let junc = rs.junction.get();
-            let junc = if junc != !0u32 && (raw_sections[junc as usize].flags.get() & 0x10 != 0) {
-                junc
-            }
-            else { !0u32 };
-            let next = [rs.next.get(), junc];
-            crate::TrackNode{prev, next, center}
+            let prev = sect.prev as u32;
+            let junc = sect.junction()
+                .filter(|&junc| {
+                    sections.get(junc as usize)
+                        .is_some_and(|j| j.flags & Section::JUNCTION_START != 0)
+                })
+                .unwrap_or(!0u32);
+            let next = [sect.next as u32, junc];
+            let faces = [sect.faces().start as u32, sect_faces.len() as u32];
+            Ok(crate::TrackNode{prev, next, center, faces})
         })
         .collect()
 }
-
@@ -0,0 +1,33 @@
+//! What differs between the data sets of the releases the bundler can read. So far that's only
+//! the original Wipeout; 2097 and XL get versions of their own once `scan` has been run over
+//! dumps of them and the differences are known rather than guessed.
+
+use formats::reader::Endian;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GameVersion {
+    #[default]
+    Wipeout,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Profile {
+    /// Byte order of the `.prm`, track and tile files; `.cmp` and `.tim` are always little-endian
+    pub endian: Endian,
+    /// Track directories are named this followed by a number
+    pub track_prefix: &'static str,
+    /// Ship models and textures, without extension
+    pub ships: &'static str,
+}
+
+impl GameVersion {
+    pub fn profile(self) -> Profile {
+        match self {
+            GameVersion::Wipeout => Profile {
+                endian:       Endian::Big,
+                track_prefix: "track",
+                ships:        "common/allsh",
+            },
+        }
+    }
+}
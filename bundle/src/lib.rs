@@ -10,7 +10,6 @@
 )]
 
 mod lzss;
-mod be;
 pub mod bundler;
 
 mod reexports {
@@ -58,10 +57,26 @@ pub struct Path {
     pub points: Vec<[f32; 3]>,
 }
 
+/// How an [`ImageSet`]'s `qoi_stream` is coded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, rkyv::Archive, rkyv::Serialize)]
+pub enum ImageCoding {
+    Qoi,
+    /// `qoit::psx`, for images that are all PSX colour
+    QoiPsx,
+}
+
 #[derive(rkyv::Archive, rkyv::Serialize)]
 pub struct ImageSet {
     pub sizes: Vec<(u16, u16)>,
+    pub coding: ImageCoding,
+    /// Every image's bare QOI ops, each coded from a fresh state so they can be decoded apart
     pub qoi_stream: Vec<u8>,
+    /// Where each image starts in `qoi_stream`
+    pub offsets: Vec<u32>,
+    /// Every texel's STP bit, image after image, eight to a byte, low bit first
+    pub stp: Vec<u8>,
+    /// Number of detail levels; the images are split evenly between them, most detailed first
+    pub lods: u8,
 }
 
 #[derive(Default, rkyv::Archive, rkyv::Serialize)]
@@ -111,10 +126,58 @@ pub struct Sprites {
     pub tex: Vec<u16>,
 }
 
+/// Line segments, in world space
+#[derive(Default, rkyv::Archive, rkyv::Serialize)]
+pub struct Lines {
+    pub ends: Vec<[[f32; 3]; 2]>,
+    pub rgb:  Vec<[un8; 3]>,
+}
+
+/// Spline knots as (control, position, control), in world space
+#[derive(Default, rkyv::Archive, rkyv::Serialize)]
+pub struct Splines {
+    pub points: Vec<[[f32; 3]; 3]>,
+    pub rgb:    Vec<[un8; 3]>,
+}
+
+#[derive(rkyv::Archive, rkyv::Serialize)]
+pub struct DirLight {
+    pub dir: [f32; 3],
+    pub rgb: [un8; 3],
+}
+
+#[derive(rkyv::Archive, rkyv::Serialize)]
+pub struct PointLight {
+    pub xyz:     [f32; 3],
+    pub rgb:     [un8; 3],
+    /// Distances at which falloff starts and ends
+    pub falloff: [f32; 2],
+}
+
+#[derive(rkyv::Archive, rkyv::Serialize)]
+pub struct SpotLight {
+    pub xyz:     [f32; 3],
+    pub dir:     [f32; 3],
+    pub rgb:     [un8; 3],
+    pub falloff: [f32; 2],
+    /// Cone and spread angles, in radians
+    pub cone:    [f32; 2],
+}
+
+#[derive(Default, rkyv::Archive, rkyv::Serialize)]
+pub struct Lights {
+    pub dir:   Vec<DirLight>,
+    pub point: Vec<PointLight>,
+    pub spot:  Vec<SpotLight>,
+}
+
 #[derive(rkyv::Archive, rkyv::Serialize)]
 pub struct Scene {
     pub mset: ModelSet,
     pub sprites: Sprites,
+    pub lines: Lines,
+    pub splines: Splines,
+    pub lights: Lights,
 }
 
 #[derive(rkyv::Archive, rkyv::Serialize)]
@@ -122,10 +185,31 @@ pub struct TrackNode {
     pub prev: u32,
     pub next: [u32; 2],
     pub center: [f32; 3],
+    /// First road face in the section, and how many
+    pub faces: [u32; 2],
 }
 
 pub type TrackGraph = Vec<TrackNode>;
 
+/// Which sections can be seen from each section, per the track's `.vew`. Empty when there's no
+/// `.vew` or it couldn't be made sense of, which means nothing is to be culled.
+#[derive(Default, rkyv::Archive, rkyv::Serialize)]
+pub struct Visibility {
+    /// start of each section's run in `sects`
+    pub sect_0: Vec<u32>,
+    pub sects: Vec<u16>,
+}
+
+impl ArchivedVisibility {
+    /// The sections visible from `sect_i`, or `None` if that's not known, in which case every
+    /// section should be taken as visible.
+    pub fn visible_from(&self, sect_i: usize) -> Option<&[u16]> {
+        let start = *self.sect_0.get(sect_i)? as usize;
+        let end = self.sect_0.get(sect_i + 1).map_or(self.sects.len(), |&i| i as usize);
+        self.sects.get(start..end)
+    }
+}
+
 #[derive(rkyv::Archive, rkyv::Serialize)]
 pub struct RoadModel {
     pub verts: Vec<[f32; 3]>,
@@ -145,6 +229,7 @@ pub struct Track {
     pub sky_mset: ModelSet,
     pub sky_iset: ImageSet,
     pub graph: TrackGraph,
+    pub visibility: Visibility,
 }
 
 #[derive(rkyv::Archive, rkyv::Serialize)]
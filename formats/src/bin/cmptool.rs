@@ -0,0 +1,68 @@
+use {
+    anyhow::{Context as _, Result as Anyhow},
+    formats::CmpArchive,
+    std::process::ExitCode,
+};
+
+const USAGE: &str = "\
+usage: cmptool list <cmp>
+       cmptool extract <cmp> <index> <out.tim>
+       cmptool pack <out.cmp> <tim>...";
+
+fn main() -> ExitCode {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+
+    let result = match &args[..] {
+        ["list", cmp_path] => list(cmp_path),
+        ["extract", cmp_path, index, out_path] => extract(cmp_path, index, out_path),
+        ["pack", out_path, tim_paths@..] => pack(out_path, tim_paths),
+        _ => {
+            eprintln!("{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("cmptool: {e:#}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn read(path: &str) -> Anyhow<Vec<u8>> {
+    std::fs::read(path).with_context(|| format!("reading {path}"))
+}
+
+/// Expands the whole archive in one pass, then describes each entry.
+fn list(cmp_path: &str) -> Anyhow<()> {
+    let cmp = read(cmp_path)?;
+    let archive = CmpArchive::parse(&cmp).with_context(|| format!("parsing {cmp_path}"))?;
+    println!("{} entries, {} bytes expanded", archive.len(), archive.expanded_size());
+    let tims = archive.read_all().with_context(|| format!("expanding {cmp_path}"))?;
+    for (entry, tim) in archive.entries().zip(&tims) {
+        let dims = formats::load_tim_raw(tim)
+            .map(|raw| format!("{}x{}", raw.wide(), raw.high()))
+            .unwrap_or_else(|e| format!("({e:#})"));
+        println!("{:4}  +{:06x}  {:7}  {dims}", entry.index, entry.offset, entry.size);
+    }
+    Ok(())
+}
+
+fn extract(cmp_path: &str, index: &str, out_path: &str) -> Anyhow<()> {
+    let index = index.parse().with_context(|| format!("bad entry index {index:?}"))?;
+    let cmp = read(cmp_path)?;
+    let archive = CmpArchive::parse(&cmp).with_context(|| format!("parsing {cmp_path}"))?;
+    let tim = archive.read(index)?;
+    std::fs::write(out_path, tim).with_context(|| format!("writing {out_path}"))
+}
+
+fn pack(out_path: &str, tim_paths: &[&str]) -> Anyhow<()> {
+    let tims = tim_paths.iter()
+        .map(|path| read(path))
+        .collect::<Anyhow<Vec<_>>>()?;
+    let cmp = CmpArchive::write(&tims)?;
+    std::fs::write(out_path, cmp).with_context(|| format!("writing {out_path}"))
+}
@@ -0,0 +1,40 @@
+//! Coverage report: what every file under a game dump is, and whether it parses.
+
+use std::{collections::BTreeMap, process::ExitCode};
+
+fn main() -> ExitCode {
+    let Some(dir) = std::env::args().nth(1) else {
+        eprintln!("usage: scan <wipeout_dir>");
+        return ExitCode::from(2);
+    };
+
+    let reports = match formats::scan(dir.as_str().into()) {
+        Ok(reports) => reports,
+        Err(e) => {
+            eprintln!("scan: {e:#}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut totals = BTreeMap::<formats::Kind, (usize, usize)>::new();
+    for report in &reports {
+        let status = match &report.parsed {
+            Ok(()) if report.kind.is_verified() => "ok".to_owned(),
+            Ok(()) => "ok, unverified".to_owned(),
+            Err(e) => format!("FAIL: {e:#}"),
+        };
+        println!("{:8} {:9} {}  {status}", report.kind, report.size, report.path);
+        let (n, n_ok) = totals.entry(report.kind).or_default();
+        *n += 1;
+        *n_ok += report.parsed.is_ok() as usize;
+    }
+
+    println!();
+    for (kind, (n, n_ok)) in &totals {
+        let unverified = if kind.is_verified() {""} else {" (known by name and size only)"};
+        println!("{kind:8} {n_ok:5} / {n:5} parse{unverified}");
+    }
+    let known = reports.iter().filter(|r| r.kind != formats::Kind::Unknown).count();
+    println!("{known} of {} files recognised", reports.len());
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,137 @@
+use {
+    crate::{lzss, reader::{self, Reader}, Texture},
+    anyhow::{Result as Anyhow, Context as _, anyhow, bail},
+    std::io::Read as _,
+};
+
+/// A `.cmp` texture archive: a table of TIM sizes, then a single LZSS stream holding all of the
+/// TIMs back to back.
+pub struct CmpArchive<'a> {
+    sizes: Vec<usize>,
+    stream: &'a [u8],
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Entry {
+    pub index: usize,
+    /// where the entry starts in the expanded stream
+    pub offset: usize,
+    pub size: usize,
+}
+
+impl<'a> CmpArchive<'a> {
+    pub fn parse(cmp: &'a [u8]) -> Result<Self, reader::Error> {
+        let r = &mut Reader::new(cmp).little();
+        let n_tims: u32 = r.field("count")?;
+        if n_tims as usize > r.remaining() / 4 {
+            return Err(r.invalid(format!("{n_tims} entries won't fit in {} bytes", cmp.len())));
+        }
+        let sizes: Vec<u32> = r.array("size", n_tims as usize)?;
+        let sizes = sizes.into_iter().map(|size| size as usize).collect();
+        Ok(CmpArchive{sizes, stream: r.rest()})
+    }
+
+    pub fn len(&self) -> usize {
+        self.sizes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sizes.is_empty()
+    }
+
+    /// Total size of all entries once expanded.
+    pub fn expanded_size(&self) -> usize {
+        self.sizes.iter().sum()
+    }
+
+    pub fn entry(&self, index: usize) -> Option<Entry> {
+        let size = *self.sizes.get(index)?;
+        let offset = self.sizes[..index].iter().sum();
+        Some(Entry{index, offset, size})
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = Entry> + '_ {
+        self.sizes.iter()
+            .scan(0, |offset, &size| {
+                let start = *offset;
+                *offset += size;
+                Some((start, size))
+            })
+            .enumerate()
+            .map(|(index, (offset, size))| Entry{index, offset, size})
+    }
+
+    /// Expands entry `index` alone, without holding on to the entries before it.
+    pub fn read(&self, index: usize) -> Anyhow<Vec<u8>> {
+        let entry = self.entry(index)
+            .ok_or(anyhow!("no entry {index}; cmp has {}", self.len()))?;
+        let mut expander = lzss::Expander::new(self.stream);
+        std::io::copy(&mut (&mut expander).take(entry.offset as u64), &mut std::io::sink())?;
+        let mut tim = vec![0; entry.size];
+        expander.read_exact(&mut tim)
+            .with_context(|| format!("cmp entry {index}"))?;
+        Ok(tim)
+    }
+
+    /// Expands every entry in one pass.
+    pub fn read_all(&self) -> Anyhow<Vec<Vec<u8>>> {
+        let mut expander = lzss::Expander::new(self.stream);
+        self.sizes.iter().enumerate()
+            .map(|(index, &size)| {
+                let mut tim = vec![0; size];
+                expander.read_exact(&mut tim)
+                    .with_context(|| format!("cmp entry {index}"))?;
+                Ok(tim)
+            })
+            .collect()
+    }
+
+    pub fn decode(&self, index: usize) -> Anyhow<Texture> {
+        let tim = self.read(index)?;
+        decode_entry(index, &tim)
+    }
+
+    pub fn decode_all(&self) -> Anyhow<Vec<Texture>> {
+        self.read_all()?.into_iter()
+            .enumerate()
+            .map(|(index, tim)| decode_entry(index, &tim))
+            .collect()
+    }
+
+    /// Builds a new archive out of raw TIMs.
+    pub fn write<Tim>(tims: &[Tim]) -> Anyhow<Vec<u8>> where Tim: AsRef<[u8]> {
+        let n_tims = u32::try_from(tims.len())?;
+        let mut cmp = Vec::new();
+        cmp.extend_from_slice(&n_tims.to_le_bytes());
+        for tim in tims {
+            let size = u32::try_from(tim.as_ref().len())?;
+            cmp.extend_from_slice(&size.to_le_bytes());
+        }
+        let stream = tims.iter()
+            .flat_map(|tim| tim.as_ref().iter().copied())
+            .collect::<Vec<_>>();
+        cmp.extend_from_slice(&lzss::compress(&stream));
+        Ok(cmp)
+    }
+}
+
+fn decode_entry(index: usize, tim: &[u8]) -> Anyhow<Texture> {
+    if tim.is_empty() {bail!("cmp entry {index} is empty")}
+    crate::load_tim(tim).with_context(|| format!("cmp entry {index}"))
+}
+
+#[cfg(test)]
+#[test]
+fn write_then_read() {
+    let blobs = [&b"first"[..], b"", b"third entry, a little longer", b"4"];
+    let cmp = CmpArchive::write(&blobs).unwrap();
+    let archive = CmpArchive::parse(&cmp).unwrap();
+    assert_eq!(archive.len(), blobs.len());
+    for (entry, blob) in archive.entries().zip(blobs) {
+        assert_eq!(entry.size, blob.len());
+        assert_eq!(archive.read(entry.index).unwrap(), blob);
+    }
+    assert_eq!(archive.read_all().unwrap(), blobs);
+    assert!(archive.decode(1).is_err());
+    assert!(archive.read(4).is_err());
+}
@@ -0,0 +1,215 @@
+//! Working out what a blob of game data is. Formats with headers are recognised by their
+//! structure alone; the bare arrays have none to speak of, so for those the file name picks the
+//! candidate and the size has to agree with it. Sections and view lists are also checked for
+//! indices that make sense, but the rest are taken on name and size alone, and reported as
+//! unverified.
+
+use {
+    crate::{lzss, prm, reader::Endian, tiles, track},
+    anyhow::{Result as Anyhow, anyhow, bail},
+    camino::{Utf8Path as Path, Utf8PathBuf as PathBuf},
+    std::io::Read as _,
+};
+
+/// Byte order of the bare arrays and `.prm`s, as in the original game's data
+const ENDIAN: Endian = Endian::Big;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Kind {
+    Tim,
+    Cmp,
+    Prm,
+    Trv,
+    Trf,
+    Trs,
+    Vew,
+    Ttf,
+    /// `.vh`
+    VabHeader,
+    /// `.vb`
+    VabBody,
+    Unknown,
+}
+
+impl Kind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Tim       => "tim",
+            Kind::Cmp       => "cmp",
+            Kind::Prm       => "prm",
+            Kind::Trv       => "trv",
+            Kind::Trf       => "trf",
+            Kind::Trs       => "trs",
+            Kind::Vew       => "vew",
+            Kind::Ttf       => "ttf",
+            Kind::VabHeader => "vh",
+            Kind::VabBody   => "vb",
+            Kind::Unknown   => "unknown",
+        }
+    }
+
+    /// Whether `identify` looks at the contents to tell this kind, rather than only at the file
+    /// name and size.
+    pub fn is_verified(self) -> bool {
+        !matches!(self, Kind::Trv | Kind::Trf | Kind::Ttf | Kind::VabBody | Kind::Unknown)
+    }
+
+    /// Parses `bytes` in full as this kind, discarding the result.
+    pub fn check(self, bytes: &[u8]) -> Anyhow<()> {
+        match self {
+            Kind::Tim       => { crate::load_tim(bytes)?; }
+            Kind::Cmp       => { crate::load_cmp(bytes)?; }
+            Kind::Prm       => { prm::load_prm(bytes, ENDIAN)?; }
+            Kind::Trv       => { track::load_trv(bytes, ENDIAN)?; }
+            Kind::Trf       => { track::load_trf(bytes, ENDIAN)?; }
+            Kind::Trs       => { track::load_trs(bytes, ENDIAN)?; }
+            Kind::Vew       => { track::load_vew(bytes, ENDIAN)?; }
+            Kind::Ttf       => { tiles::load_ttf(bytes, ENDIAN)?; }
+            Kind::VabHeader => { spu_adpcm::Vab::parse(bytes)?; }
+            Kind::VabBody   => {
+                for sample in spu_adpcm::split_samples(bytes)? {
+                    spu_adpcm::decode(sample)?;
+                }
+            }
+            Kind::Unknown   => bail!("unrecognised"),
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for Kind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+pub fn identify(bytes: &[u8], file_name: &str) -> Kind {
+    if is_tim_header(bytes) {return Kind::Tim}
+    if bytes.starts_with(b"pBAV") {return Kind::VabHeader}
+    if is_cmp(bytes) {return Kind::Cmp}
+
+    let ext = file_name.rsplit_once('.').map(|(_, ext)| ext.to_ascii_lowercase());
+    let (kind, record) = match ext.as_deref() {
+        Some("trv") => (Kind::Trv, 16),
+        Some("trf") => (Kind::Trf, 20),
+        Some("trs") => (Kind::Trs, 156),
+        Some("vew") => (Kind::Vew, 2),
+        Some("ttf") => (Kind::Ttf, 42),
+        Some("vb")  => (Kind::VabBody, 16),
+        _           => (Kind::Unknown, 0),
+    };
+    let fits = !bytes.is_empty() && bytes.len().checked_rem(record) == Some(0);
+    if fits && indices_in_range(kind, bytes) {return kind}
+
+    if prm::load_prm(bytes, ENDIAN).is_ok_and(|objs| !objs.is_empty()) {return Kind::Prm}
+
+    Kind::Unknown
+}
+
+/// Checks the 8-byte TIM header: magic, then a pixel type whose CLUT flag agrees with it.
+fn is_tim_header(bytes: &[u8]) -> bool {
+    let Some(head) = bytes.get(..8) else {return false};
+    let flags = u32::from_le_bytes(head[4..8].try_into().unwrap());
+    let pixel_type = flags & 3;
+    let got_clut = flags & 8 != 0;
+    head[..4] == [0x10, 0, 0, 0] && flags & !0xb == 0 && got_clut == (pixel_type < 2)
+}
+
+/// Checks that every section links only to sections that exist, and that view lists hold only
+/// section indices. Other kinds pass; see [`Kind::is_verified`].
+fn indices_in_range(kind: Kind, bytes: &[u8]) -> bool {
+    match kind {
+        Kind::Trs => track::load_trs(bytes, ENDIAN).is_ok_and(|sects| {
+            let n = sects.len() as i32;
+            sects.iter()
+                .flat_map(|sect| [sect.junction, sect.prev, sect.next])
+                .all(|si| (-1..n).contains(&si))
+        }),
+        Kind::Vew => track::load_vew(bytes, ENDIAN)
+            .is_ok_and(|vew| vew.iter().all(|&si| si >= -1)),
+        _         => true,
+    }
+}
+
+/// Checks that the size table fits and that the first entry expands to a TIM header.
+fn is_cmp(bytes: &[u8]) -> bool {
+    let Ok(archive) = crate::CmpArchive::parse(bytes) else {return false};
+    if archive.is_empty() {return false}
+    let stream = &bytes[4 + archive.len() * 4 ..];
+    let mut head = [0; 8];
+    lzss::Expander::new(stream).read_exact(&mut head).is_ok() && is_tim_header(&head)
+}
+
+/// What `scan` found out about one file.
+pub struct Report {
+    /// relative to the scanned directory
+    pub path: PathBuf,
+    pub size: usize,
+    pub kind: Kind,
+    /// whether the file could be read and parsed as `kind`
+    pub parsed: Anyhow<()>,
+}
+
+/// Identifies and test-parses every file under `dir`, in path order. A file that can't be read
+/// is reported as such rather than ending the scan.
+pub fn scan(dir: &Path) -> Anyhow<Vec<Report>> {
+    let mut paths = Vec::new();
+    let mut dirs = vec![dir.to_owned()];
+    while let Some(dir) = dirs.pop() {
+        for entry in dir.read_dir_utf8()? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {dirs.push(entry.into_path())}
+            else                           {paths.push(entry.into_path())}
+        }
+    }
+    paths.sort_unstable();
+
+    let reports = paths.into_iter()
+        .map(|path| {
+            let rel = path.strip_prefix(dir).map_or(path.clone(), Path::to_owned);
+            match std::fs::read(&path) {
+                Ok(bytes) => {
+                    let kind = identify(&bytes, path.file_name().unwrap_or(""));
+                    let parsed = kind.check(&bytes);
+                    Report{path: rel, size: bytes.len(), kind, parsed}
+                }
+                Err(e) => {
+                    let parsed = Err(anyhow!(e).context("reading"));
+                    Report{path: rel, size: 0, kind: Kind::Unknown, parsed}
+                }
+            }
+        })
+        .collect();
+    Ok(reports)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kinds() {
+        let tim = crate::encode_tim(
+            &pixmap::Pixmap::new(4, 2, pixmap::Rgba::WHITE),
+            crate::TimDepth::Indexed4,
+        ).unwrap();
+        assert_eq!(identify(&tim, "whatever"), Kind::Tim);
+
+        let cmp = crate::CmpArchive::write(&[&tim, &tim]).unwrap();
+        assert_eq!(identify(&cmp, "LIBRARY.CMP"), Kind::Cmp);
+
+        assert_eq!(identify(&[0; 156 * 2], "TRACK.TRS"), Kind::Trs);
+        assert_eq!(identify(&[0; 155], "TRACK.TRS"), Kind::Unknown);
+        let mut trs = [0; 156 * 2];
+        trs[156 + 8 .. 156 + 12].copy_from_slice(&2i32.to_be_bytes());
+        assert_eq!(identify(&trs, "TRACK.TRS"), Kind::Unknown);
+        assert!(Kind::Trs.is_verified() && !Kind::Trf.is_verified());
+        assert_eq!(identify(&[0; 40], "track.vew"), Kind::Vew);
+        assert_eq!(identify(&[0xff; 40], "track.vew"), Kind::Vew);
+        assert_eq!(identify(&[0xfe; 40], "track.vew"), Kind::Unknown);
+        assert_eq!(identify(b"not much", "readme.txt"), Kind::Unknown);
+
+        assert!(Kind::Cmp.check(&cmp).is_ok());
+        assert!(Kind::Tim.check(&tim[..tim.len() - 1]).is_err());
+    }
+}
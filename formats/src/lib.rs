@@ -1,116 +1,284 @@
 #![feature(array_chunks)]
+#![feature(array_try_from_fn)]
 #![feature(array_zip)]
 #![feature(int_roundings)]
 #![feature(iter_array_chunks)]
 
+pub mod cmp;
+mod identify;
 pub mod lzss;
+pub mod prm;
+pub mod reader;
+pub mod tiles;
+mod tim;
+pub mod track;
+
+pub use {
+    cmp::CmpArchive,
+    identify::{Kind, Report, identify, scan},
+    tim::{TimDepth, encode_tim},
+};
 
 use {
-    anyhow::{Result as Anyhow, anyhow, bail},
-    bytemuck::pod_read_unaligned as read,
-    pixmap::{Pixmap, Rgba},
+    anyhow::Result as Anyhow,
+    pixmap::{Color, Index, Indexed, Pixel, Pixmap, Rgb, Rgb555, Rgba},
+    reader::{Reader, Skip},
 };
 
 pub type Image = Pixmap<Vec<Rgba>>;
 
-pub fn load_cmp(cmp: &[u8]) -> Anyhow<Vec<Image>> {
-    let n_tims: u32 = read(&cmp[0..4]);
-    let (lens, data) = cmp[4..].split_at(n_tims as usize * 4);
-    let mut tims = lzss::expand(data);
-    lens.array_chunks()
-        .enumerate()
-        .map(|(i, &len)| {
-            let len = u32::from_le_bytes(len) as usize;
-            assert_ne!(len, 0);
-            let rest = tims.split_off(len);
-            let tim = std::mem::replace(&mut tims, rest);
-            load_tim(&tim)
-        })
-        .collect()
+/// How a texel is drawn, which on the PSX takes both the texel and the primitive drawing it.
+///
+/// `0x0000` is never drawn. A texel with its STP bit set is blended only if the primitive is
+/// semi-transparent, in the mode that the primitive's ABR bits select; otherwise it's opaque,
+/// which makes `0x8000` the usual way to get opaque black. See [`Blend::of_texel`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum Blend {
+    Opaque     = 0,
+    /// `0x0000`; never drawn
+    CutOut     = 1,
+    /// `B/2 + F/2`
+    Average    = 2,
+    /// `B + F`
+    Add        = 3,
+    /// `B - F`
+    Subtract   = 4,
+    /// `B + F/4`
+    AddQuarter = 5,
+}
+
+impl Blend {
+    pub fn is_semi(self) -> bool {
+        self as u8 >= Blend::Average as u8
+    }
+
+    /// The mode a semi-transparent primitive's two ABR bits select
+    pub fn from_abr(abr: u8) -> Blend {
+        match abr & 3 {
+            0 => Blend::Average,
+            1 => Blend::Add,
+            2 => Blend::Subtract,
+            _ => Blend::AddQuarter,
+        }
+    }
+
+    /// How `word` is drawn by a primitive with the given ABR bits, or `None` for an opaque one.
+    pub fn of_texel(word: Rgb555, abr: Option<u8>) -> Blend {
+        match abr {
+            _ if word == Rgb555::TRANSPARENT => Blend::CutOut,
+            Some(abr) if word.stp()          => Blend::from_abr(abr),
+            _                                => Blend::Opaque,
+        }
+    }
+}
+
+/// A decoded TIM along with the STP bit of each of its pixels, in row-major order.
+///
+/// `0x0000` decodes as transparent and every other word as opaque. What STP means is up to the
+/// primitive, so it's kept aside for [`Blend::of_texel`] rather than folded into the alpha.
+pub struct Texture {
+    pub image: Image,
+    pub stp: Vec<bool>,
+}
+
+pub fn load_cmp(cmp: &[u8]) -> Anyhow<Vec<Texture>> {
+    CmpArchive::parse(cmp)?.decode_all()
+}
+
+/// A TIM's pixels as stored, before expanding them to RGBA
+pub enum RawTim {
+    /// 4- or 8-bit indices into the CLUT
+    Indexed(Indexed<Rgb555>),
+    Direct15(Pixmap<Vec<Rgb555>, Rgb555>),
+    Direct24(Pixmap<Vec<Rgb>, Rgb>),
+}
+
+impl RawTim {
+    pub fn wide(&self) -> i32 {
+        match self {
+            RawTim::Indexed(indexed) => indexed.wide(),
+            RawTim::Direct15(words)  => words.wide(),
+            RawTim::Direct24(rgbs)   => rgbs.wide(),
+        }
+    }
+
+    pub fn high(&self) -> i32 {
+        match self {
+            RawTim::Indexed(indexed) => indexed.high(),
+            RawTim::Direct15(words)  => words.high(),
+            RawTim::Direct24(rgbs)   => rgbs.high(),
+        }
+    }
+
+    /// Expands the pixels to RGBA, keeping each one's STP bit.
+    pub fn to_texture(&self) -> Texture {
+        let from_words = |words: &Pixmap<Vec<Rgb555>, Rgb555>| {
+            let stp = words.try_as_slice().unwrap().iter().map(|word| word.stp()).collect();
+            let image = Pixmap::new_from_fn(words.wide(), words.high(), |xy| {
+                texel(words.get(xy).unwrap())
+            });
+            Texture{image, stp}
+        };
+        match self {
+            RawTim::Indexed(indexed) => from_words(&indexed.to_pixmap()),
+            RawTim::Direct15(words)  => from_words(words),
+            RawTim::Direct24(rgbs)   => {
+                let stp = vec![false; (rgbs.wide() * rgbs.high()) as usize];
+                Texture{image: rgbs.convert(), stp}
+            }
+        }
+    }
+}
+
+/// `word` as RGBA, opaque unless it's `0x0000`, whatever its STP bit; see [`Texture`].
+fn texel(word: Rgb555) -> Rgba {
+    if word == Rgb555::TRANSPARENT {return Rgba::TRANSPARENT}
+    let Rgba([r, g, b, _]) = word.to_rgba();
+    Rgba([r, g, b, 0xff])
 }
 
-pub fn load_tim(tim: &[u8]) -> Anyhow<Image> {
-    if tim[0] != 0x10 || tim[1] != 0x00 {bail!("not a tim")}
-    let pixel_type = tim[4] & 3;
-    let got_clut = tim[4] & 8 != 0;
-    if got_clut != (pixel_type < 2) {bail!("inconsistent pixel type and clut presence flag")}
-    if got_clut {from_indexed(tim, pixel_type)}
-    else        {from_direct(tim, pixel_type)}
+pub fn load_tim(tim: &[u8]) -> Anyhow<Texture> {
+    Ok(load_tim_raw(tim)?.to_texture())
 }
 
-fn from_indexed(tim: &[u8], pixel_type: u8) -> Anyhow<Image> {
-    let bs = &tim[8..];
+/// Loads a TIM, keeping its CLUT and colour words as they are.
+pub fn load_tim_raw(tim: &[u8]) -> Anyhow<RawTim> {
+    let r = &mut Reader::new(tim).little();
+    let (pixel_type, got_clut) = r.with("TimHeader", |r| {
+        let head: TimHeader = r.read()?;
+        if head.magic & 0xffff != 0x10 {return Err(r.invalid("not a tim"))}
+        let pixel_type = (head.flags & 3) as u8;
+        let got_clut = head.flags & 8 != 0;
+        if got_clut != (pixel_type < 2) {
+            return Err(r.invalid("inconsistent pixel type and clut presence flag"))
+        }
+        Ok((pixel_type, got_clut))
+    })?;
+    if got_clut {from_indexed(r, pixel_type)}
+    else        {from_direct(r, pixel_type)}
+}
+
+crate::layout! {
+    struct TimHeader {
+        magic: u32,
+        flags: u32,
+    }
+
+    /// Header of a CLUT or image block; `len` includes the header, and `wide` is in 16-bit units
+    struct BlockHeader {
+        len:  u32,
+        _xy:  Skip<4>,
+        wide: u16,
+        high: u16,
+    }
+}
 
+fn from_indexed(r: &mut Reader, pixel_type: u8) -> Anyhow<RawTim> {
     debug_assert!(pixel_type < 2);
     let four_bit = pixel_type == 0;
 
-    let (clut, bs) = {
-        let clut_len_bs = bs.get(0..4).ok_or(anyhow!("truncated tim"))?;
-        let clut_len = read::<u32>(clut_len_bs) as usize;
-        if bs.len() < clut_len {bail!("truncated tim")}
-        bs.split_at(clut_len)
-    };
-
-    let clut = {
-        let clut = clut.get(12..).ok_or(anyhow!("truncated tim"))?;
+    let clut = r.with("clut", |r| {
+        let head: BlockHeader = r.read()?;
         let pal_n = if four_bit {16} else {256};
-        if clut.len() != pal_n * 2 {bail!("wrong sized clut")}
-        clut.array_chunks().copied()
-            .map(u16::from_le_bytes)
-            .map(rgb15x1_to_rgba8)
-            .collect::<Vec<_>>()
-    };
+        if head.len as usize != 12 + pal_n * 2 {return Err(r.invalid("wrong sized clut"))}
+        let words: Vec<u16> = r.array("colour", pal_n)?;
+        Ok(words.into_iter().map(Rgb555).collect::<Vec<_>>())
+    })?;
 
-    if bs.len() < 12 {bail!("truncated tim")}
-    let (header, bs) = bs.split_at(12);
-    let data_len = read::<u32>(&header[0..4]) as usize - 12;
-    let wide = read::<u16>(&header[ 8..10]);
-    let high = read::<u16>(&header[10..12]);
-    let data = bs.get(..data_len).ok_or(anyhow!("truncated tim"))?;
+    let (wide, high, data) = image_block(r)?;
 
     //let wide = wide.next_multiple_of(2);
 
-    let (wide, mut pixels) = if four_bit {
-        let pixels = data.iter().copied()//chunks_exact((wide as usize * 2).next_multiple_of(4))
+    let (wide, indices) = if four_bit {
+        let indices = data.iter().copied()//chunks_exact((wide as usize * 2).next_multiple_of(4))
             //.flat_map(|row| &row[..wide as usize * 2])
-            .flat_map(|b| [(b & 0xf) as usize, (b >> 4) as usize])
-            .map(|i| clut[i])
+            .flat_map(|b| [Index(b & 0xf), Index(b >> 4)])
             .collect::<Vec<_>>();
-        (wide * 4, pixels)
+        (wide as usize * 4, indices)
     }
     else {
-        let pixels = data.iter().copied()//chunks_exact((wide as usize * 2).next_multiple_of(4))
+        let indices = data.iter().copied()//chunks_exact((wide as usize * 2).next_multiple_of(4))
             //.flat_map(|row| &row[..wide as usize * 2])
-            .map(|i| clut[i as usize])
+            .map(Index)
             .collect::<Vec<_>>();
-        (wide * 2, pixels)
+        (wide as usize * 2, indices)
     };
 
-    //debug_assert_eq!(pixels.len(), (wide * high) as usize);
-    pixels.resize(wide as usize * high as usize, Rgba::TRANSPARENT);
-    let pm = Pixmap::new_from_pixels(pixels, 0, 1, wide.into(), high.into()).unwrap();
-    Ok(pm)
+    let indices = make_pixmap(indices, wide, high, Index(0));
+    // indices are only as wide as the clut is long
+    Ok(RawTim::Indexed(Indexed::new(indices, clut).unwrap()))
 }
 
-fn from_direct(_bs: &[u8], pixel_type: u8) -> Anyhow<Image> {
+fn from_direct(r: &mut Reader, pixel_type: u8) -> Anyhow<RawTim> {
     debug_assert!(pixel_type >= 2);
-    todo!("direct-colour tims")
-}
+    let (wide, high, data) = image_block(r)?;
 
-fn rgb15x1_to_rgba8(bits: u16) -> Rgba {
-    if (bits >> 15) & 0x01 != 0 || (bits & 0x7fff) == 0 {
-        return Rgba::TRANSPARENT;
+    if pixel_type == 2 {
+        let words = data.array_chunks().copied()
+            .map(u16::from_le_bytes)
+            .map(Rgb555)
+            .collect::<Vec<_>>();
+        Ok(RawTim::Direct15(make_pixmap(words, wide as usize, high, Rgb555::TRANSPARENT)))
+    }
+    else {
+        // rows are padded out to a whole number of 16-bit units
+        let row_len = wide as usize * 2;
+        let wide = row_len / 3;
+        let texels = data.chunks_exact(row_len)
+            .flat_map(|row| row.array_chunks::<3>().take(wide))
+            .map(|&rgb| Rgb(rgb))
+            .collect::<Vec<_>>();
+        Ok(RawTim::Direct24(make_pixmap(texels, wide, high, Rgb([0; 3]))))
     }
+}
 
-    let ramp = |y: f32| y;
+/// Pads or cuts `pixels` to fit. `image_block` keeps the sizes small enough for an `i32`.
+fn make_pixmap<P: Pixel>(mut pixels: Vec<P>, wide: usize, high: u16, fill: P) -> Pixmap<Vec<P>, P> {
+    //debug_assert_eq!(pixels.len(), (wide * high) as usize);
+    pixels.resize(wide * high as usize, fill);
+    Pixmap::new_from_pixels(pixels, 0, 1, wide as i32, high.into()).unwrap()
+}
 
-    let chan = |i: u16| {
-        let y0 = ((bits >> i) & 0x1f) as f32 / 31.;
-        let y1 = ramp(y0);
-        (y1 * 255.) as u8
-    };
+/// VRAM size in 16-bit units, which no image block can be bigger than
+const VRAM_DIMS: [u16; 2] = [1024, 512];
 
-    let [r, g, b] = [0, 5, 10].map(chan);
-    [r, g, b, 255].into()
+/// Reads an image block, returning its width in 16-bit units, height and pixel data.
+fn image_block<'a>(r: &mut Reader<'a>) -> Result<(u16, u16, &'a [u8]), reader::Error> {
+    r.with("image", |r| {
+        let head: BlockHeader = r.read()?;
+        let [max_wide, max_high] = VRAM_DIMS;
+        if head.wide == 0 || head.wide > max_wide || head.high > max_high {
+            let msg = format!("{}x{} image block doesn't fit in vram", head.wide, head.high);
+            return Err(r.invalid(msg));
+        }
+        let data_len = (head.len as usize).checked_sub(12)
+            .ok_or_else(|| r.invalid("bad image block length"))?;
+        let data = r.bytes(data_len)?;
+        Ok((head.wide, head.high, data))
+    })
 }
 
+
+#[cfg(test)]
+#[test]
+fn bad_image_blocks() {
+    let tim = |flags: u32, wide: u16, high: u16| {
+        let mut tim = [0x10, flags].map(u32::to_le_bytes).concat();
+        if flags & 8 != 0 {
+            tim.extend((12u32 + 32).to_le_bytes());
+            tim.extend([0, 0, 0, 0, 16, 0, 1, 0]);
+            tim.extend([0; 32]);
+        }
+        tim.extend(12u32.to_le_bytes());
+        tim.extend([0; 4]);
+        tim.extend(wide.to_le_bytes());
+        tim.extend(high.to_le_bytes());
+        tim
+    };
+    assert!(load_tim(&tim(3, 0, 4)).is_err());
+    assert!(load_tim(&tim(2, 0xffff, 0xffff)).is_err());
+    assert!(load_tim(&tim(8, 0xffff, 1)).is_err());
+    assert!(load_tim(&tim(3, 3, 2)).is_ok_and(|tex| tex.image.wide() == 2));
+    assert!(load_tim(&tim(8, 1024, 512)).is_ok_and(|tex| tex.image.wide() == 4096));
+}
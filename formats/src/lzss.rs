@@ -4,50 +4,493 @@ const WIN_LEN: usize = 1 << IDX_BITS;
 const BREAKEVEN: usize = (IDX_BITS + LEN_BITS + 1) / 9;
 const EOSTREAM: usize = 0;
 
-pub fn expand(bs: &[u8]) -> Vec<u8> {
-    let mut read_bits = {
-        let mut bs = bs.iter().copied();
-        let mut in_mask = 0x80;
-        let mut in_rack = 0;
-
-        move |n| {
-            let mut mask = 1u32 << (n - 1);
-            let mut value = 0u32;
-            while mask != 0 {
-                if in_mask == 0x80 {in_rack = bs.next().unwrap() as u32;}
-                if (in_mask & in_rack) != 0 {value |= mask;}
-                mask >>= 1;
-                in_mask >>= 1;
-                if in_mask == 0 {in_mask = 0x80}
-            }
-            value
-        }
-    };
-
-    let mut cur_pos = 1;
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("lzss stream ends early at input offset {offset:#x}")]
+    Truncated{offset: usize},
+
+    #[error("error reading lzss stream at input offset {offset:#x}")]
+    Io{offset: usize, #[source] source: std::io::Error},
+}
+
+impl Error {
+    /// How far into the compressed input the error occurred.
+    pub fn offset(&self) -> usize {
+        match *self {
+            Error::Truncated{offset} | Error::Io{offset, ..} => offset,
+        }
+    }
+}
+
+impl From<Error> for std::io::Error {
+    fn from(e: Error) -> Self {
+        let kind = match e {
+            Error::Truncated{..} => std::io::ErrorKind::UnexpectedEof,
+            Error::Io{ref source, ..} => source.kind(),
+        };
+        std::io::Error::new(kind, e)
+    }
+}
+
+pub fn expand(bs: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut bs = bs.iter().copied();
+    let mut next_in = || Ok(bs.next());
+    let mut dec = Decoder::new();
     let mut out = Vec::new();
-    let mut window = [0u8; WIN_LEN];
+    while let Some(byte) = dec.next(&mut next_in)? {
+        out.push(byte);
+    }
+    Ok(out)
+}
+
+/// Expands an LZSS stream lazily as it's read.
+pub struct Expander<R> {
+    inner: R,
+    buf: Box<[u8]>,
+    head: usize,
+    tail: usize,
+    dec: Decoder,
+}
+
+impl<R> Expander<R> where R: std::io::Read {
+    pub fn new(inner: R) -> Self {
+        let buf = vec![0; 0x1000].into_boxed_slice();
+        Expander{inner, buf, head: 0, tail: 0, dec: Decoder::new()}
+    }
+
+    /// Number of compressed bytes consumed so far.
+    pub fn input_offset(&self) -> usize {
+        self.dec.offset
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R> std::io::Read for Expander<R> where R: std::io::Read {
+    fn read(&mut self, out: &mut [u8]) -> std::io::Result<usize> {
+        let Expander{inner, buf, head, tail, dec} = self;
+        let mut next_in = || {
+            if head == tail {
+                *tail = inner.read(buf)?;
+                *head = 0;
+                if *tail == 0 {return Ok(None)}
+            }
+            let byte = buf[*head];
+            *head += 1;
+            Ok(Some(byte))
+        };
+
+        // the decoder keeps its place on errors, so anything already expanded is handed out first
+        // and the error comes again on the next read
+        let mut n = 0;
+        for slot in out.iter_mut() {
+            match dec.next(&mut next_in) {
+                Ok(Some(byte)) => *slot = byte,
+                Ok(None) => break,
+                Err(_) if n != 0 => break,
+                Err(e) => return Err(e.into()),
+            }
+            n += 1;
+        }
+        Ok(n)
+    }
+}
+
+/// Which field of a symbol `Decoder::next` is reading
+#[derive(Clone, Copy)]
+enum Stage {
+    Flag,
+    Literal,
+    Index,
+    Length{pos: usize},
+}
+
+struct Decoder {
+    window: Box<[u8; WIN_LEN]>,
+    cur_pos: usize,
+    in_mask: u8,
+    in_rack: u8,
+    offset: usize,
+    stage: Stage,
+    // bits of the current field read so far, and how many, kept across input errors
+    partial: (u32, usize),
+    // window position and remaining length of the match being copied out
+    copy: (usize, usize),
+    done: bool,
+}
 
-    loop {
-        let literal = read_bits(1) != 0;
-        if literal {
-            let byte = read_bits(8) as u8;
-            out.push(byte);
-            window[cur_pos] = byte;
-            cur_pos = (cur_pos + 1) % window.len();
+impl Decoder {
+    fn new() -> Self {
+        Decoder {
+            window: Box::new([0; WIN_LEN]),
+            cur_pos: 1,
+            in_mask: 0x80,
+            in_rack: 0,
+            offset: 0,
+            stage: Stage::Flag,
+            partial: (0, 0),
+            copy: (0, 0),
+            done: false,
+        }
+    }
+
+    /// Reads an `n`-bit field. If the input fails partway, the bits read so far are kept, and
+    /// calling again with the same `n` carries on.
+    fn read_bits<F>(&mut self, n: usize, next_in: &mut F) -> Result<u32, Error> where
+        F: FnMut() -> std::io::Result<Option<u8>>,
+    {
+        while self.partial.1 < n {
+            if self.in_mask == 0x80 {
+                let offset = self.offset;
+                self.in_rack = next_in()
+                    .map_err(|source| Error::Io{offset, source})?
+                    .ok_or(Error::Truncated{offset})?;
+                self.offset += 1;
+            }
+            let (value, got) = self.partial;
+            let bit = (self.in_mask & self.in_rack != 0) as u32;
+            self.partial = (value << 1 | bit, got + 1);
+            self.in_mask >>= 1;
+            if self.in_mask == 0 {self.in_mask = 0x80}
+        }
+        let (value, _) = std::mem::take(&mut self.partial);
+        Ok(value)
+    }
+
+    fn emit(&mut self, byte: u8) -> u8 {
+        self.window[self.cur_pos] = byte;
+        self.cur_pos = (self.cur_pos + 1) % WIN_LEN;
+        byte
+    }
+
+    fn next<F>(&mut self, next_in: &mut F) -> Result<Option<u8>, Error> where
+        F: FnMut() -> std::io::Result<Option<u8>>,
+    {
+        loop {
+            let (pos, len) = self.copy;
+            if len != 0 {
+                let byte = self.window[pos];
+                self.copy = ((pos + 1) % WIN_LEN, len - 1);
+                return Ok(Some(self.emit(byte)));
+            }
+
+            if self.done {return Ok(None)}
+
+            match self.stage {
+                Stage::Flag => {
+                    let literal = self.read_bits(1, next_in)? != 0;
+                    self.stage = if literal {Stage::Literal} else {Stage::Index};
+                }
+                Stage::Literal => {
+                    let byte = self.read_bits(8, next_in)? as u8;
+                    self.stage = Stage::Flag;
+                    return Ok(Some(self.emit(byte)));
+                }
+                Stage::Index => {
+                    let pos = self.read_bits(IDX_BITS, next_in)? as usize;
+                    if pos == EOSTREAM {
+                        self.done = true;
+                        return Ok(None);
+                    }
+                    self.stage = Stage::Length{pos};
+                }
+                Stage::Length{pos} => {
+                    let len = self.read_bits(LEN_BITS, next_in)? as usize + BREAKEVEN;
+                    self.copy = (pos, len + 1);
+                    self.stage = Stage::Flag;
+                }
+            }
+        }
+    }
+}
+
+const MIN_MATCH: usize = BREAKEVEN + 1;
+const MAX_MATCH: usize = (1 << LEN_BITS) - 1 + MIN_MATCH;
+const MAX_CHAIN: usize = 256;
+const HASH_BITS: usize = 13;
+
+/// Compresses `bs` into a stream that `expand` (and the game's own decoder) will accept.
+pub fn compress(bs: &[u8]) -> Vec<u8> {
+    let mut out = BitSink::default();
+    let mut matcher = Matcher::new(bs);
+
+    let mut i = 0;
+    while i < bs.len() {
+        let (len, src) = matcher.find(i);
+        if len >= MIN_MATCH {
+            out.put(0, 1);
+            out.put(slot(src) as u32, IDX_BITS);
+            out.put((len - MIN_MATCH) as u32, LEN_BITS);
+            (i .. i + len).for_each(|j| matcher.insert(j));
+            i += len;
         }
         else {
-            let pos = read_bits(IDX_BITS) as usize;
-            if pos == EOSTREAM {break out}
-            let len = read_bits(LEN_BITS) as usize + BREAKEVEN;
+            out.put(1, 1);
+            out.put(bs[i] as u32, 8);
+            matcher.insert(i);
+            i += 1;
+        }
+    }
+
+    out.put(0, 1);
+    out.put(EOSTREAM as u32, IDX_BITS);
+    out.finish()
+}
+
+/// Window slot of input byte `i`, as seen by the expander.
+fn slot(i: usize) -> usize {
+    (i + 1) % WIN_LEN
+}
+
+/// Hash chains over the last window's worth of input.
+struct Matcher<'a> {
+    bs: &'a [u8],
+    heads: Vec<usize>,
+    chain: Vec<usize>,
+}
+
+impl<'a> Matcher<'a> {
+    fn new(bs: &'a [u8]) -> Self {
+        let heads = vec![usize::MAX; 1 << HASH_BITS];
+        let chain = vec![usize::MAX; WIN_LEN];
+        Matcher{bs, heads, chain}
+    }
+
+    fn hash(&self, i: usize) -> usize {
+        let bs = self.bs;
+        let h = (bs[i] as usize) << 8 ^ (bs[i+1] as usize) << 4 ^ bs[i+2] as usize;
+        h & ((1 << HASH_BITS) - 1)
+    }
 
-            for i in 0 ..= len {
-                let out_byte = window[(pos + i) % window.len()];
-                out.push(out_byte);
-                window[cur_pos] = out_byte;
-                cur_pos = (cur_pos + 1) % window.len();
+    fn insert(&mut self, i: usize) {
+        if i + MIN_MATCH > self.bs.len() {return}
+        let h = self.hash(i);
+        self.chain[i % WIN_LEN] = self.heads[h];
+        self.heads[h] = i;
+    }
+
+    /// Finds the longest earlier match for the input at `i`, returning its length and start.
+    fn find(&self, i: usize) -> (usize, usize) {
+        let bs = self.bs;
+        let (mut best_len, mut best_src) = (0, 0);
+        if i + MIN_MATCH > bs.len() {return (best_len, best_src)}
+
+        let mut src = self.heads[self.hash(i)];
+        let mut steps = 0;
+        while src != usize::MAX && i - src < WIN_LEN && steps < MAX_CHAIN {
+            // slot 0 can't be referenced; it means end-of-stream
+            if slot(src) != EOSTREAM {
+                let len = (0 .. MAX_MATCH.min(bs.len() - i))
+                    .take_while(|&k| bs[src + k] == bs[i + k])
+                    .count();
+                if len > best_len {
+                    (best_len, best_src) = (len, src);
+                    if len == MAX_MATCH {break}
+                }
+            }
+            src = self.chain[src % WIN_LEN];
+            steps += 1;
+        }
+
+        (best_len, best_src)
+    }
+}
+
+struct BitSink {
+    out: Vec<u8>,
+    rack: u8,
+    mask: u8,
+}
+
+impl Default for BitSink {
+    fn default() -> Self {
+        BitSink{out: Vec::new(), rack: 0, mask: 0x80}
+    }
+}
+
+impl BitSink {
+    fn put(&mut self, value: u32, n: usize) {
+        for bit in (0..n).rev() {
+            if (value >> bit) & 1 != 0 {self.rack |= self.mask;}
+            self.mask >>= 1;
+            if self.mask == 0 {
+                self.out.push(self.rack);
+                self.rack = 0;
+                self.mask = 0x80;
             }
         }
     }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.mask != 0x80 {self.out.push(self.rack);}
+        self.out
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn noise(seed: u64, n: usize) -> Vec<u8> {
+        let mut x = seed | 1;
+        (0..n).map(|_| {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            x as u8
+        })
+        .collect()
+    }
+
+    fn round_trip(bs: &[u8]) -> Vec<u8> {
+        let packed = compress(bs);
+        let unpacked = expand(&packed).unwrap();
+        assert_eq!(unpacked.len(), bs.len());
+        assert!(unpacked == bs, "round trip mismatch");
+        packed
+    }
+
+    #[test]
+    fn empty() {
+        let packed = round_trip(&[]);
+        assert_eq!(packed.len(), 2);
+    }
+
+    #[test]
+    fn short() {
+        round_trip(b"a");
+        round_trip(b"ab");
+        round_trip(b"abc");
+        round_trip(b"abcabcabcabc");
+    }
+
+    #[test]
+    fn runs() {
+        let packed = round_trip(&[0; 100_000]);
+        assert!(packed.len() < 100_000 / 7);
+        round_trip(&[0xff; 8193]);
+    }
+
+    #[test]
+    fn noisy() {
+        for seed in 0..8 {
+            round_trip(&noise(seed, 20_000));
+        }
+    }
+
+    #[test]
+    fn repeats_across_window() {
+        // a period a little shorter and a little longer than the window
+        for period in [WIN_LEN - 5, WIN_LEN + 5] {
+            let block = noise(period as u64, period);
+            let bs = block.iter().cycle().take(period * 3 + 17).copied().collect::<Vec<_>>();
+            let packed = round_trip(&bs);
+            if period < WIN_LEN {assert!(packed.len() < bs.len() / 2)}
+        }
+    }
+
+    #[test]
+    fn structured() {
+        let bs = (0..50_000u32)
+            .flat_map(|i| [(i % 7) as u8, (i % 13) as u8, (i / 97) as u8])
+            .collect::<Vec<_>>();
+        round_trip(&bs);
+    }
+
+    fn expand_streaming(bs: &[u8], chunk: usize) -> Result<Vec<u8>, std::io::Error> {
+        use std::io::Read as _;
+
+        // hands out at most `chunk` bytes per read, to exercise refilling
+        struct Trickle<'a>(&'a [u8], usize);
+        impl std::io::Read for Trickle<'_> {
+            fn read(&mut self, out: &mut [u8]) -> std::io::Result<usize> {
+                let n = self.0.len().min(out.len()).min(self.1);
+                out[..n].copy_from_slice(&self.0[..n]);
+                self.0 = &self.0[n..];
+                Ok(n)
+            }
+        }
+
+        let mut out = Vec::new();
+        Expander::new(Trickle(bs, chunk)).read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn streaming_matches_expand() {
+        let bs = noise(3, 30_000).iter().map(|b| b & 0x0f).collect::<Vec<_>>();
+        let packed = compress(&bs);
+        for chunk in [1, 7, 0x1000] {
+            assert!(expand_streaming(&packed, chunk).unwrap() == bs);
+        }
+    }
+
+    #[test]
+    fn resumes_after_errors() {
+        use std::io::{ErrorKind, Read as _};
+
+        // fails every other read, alternating kinds, and hands out a byte or two otherwise
+        struct Flaky<'a>(&'a [u8], usize);
+        impl std::io::Read for Flaky<'_> {
+            fn read(&mut self, out: &mut [u8]) -> std::io::Result<usize> {
+                self.1 += 1;
+                match self.1 % 4 {
+                    1 => return Err(ErrorKind::WouldBlock.into()),
+                    3 => return Err(ErrorKind::Interrupted.into()),
+                    _ => (),
+                }
+                let n = self.0.len().min(out.len()).min(1 + self.1 % 3);
+                out[..n].copy_from_slice(&self.0[..n]);
+                self.0 = &self.0[n..];
+                Ok(n)
+            }
+        }
+
+        let bs = noise(5, 10_000).iter().map(|b| b & 0x07).collect::<Vec<_>>();
+        let packed = compress(&bs);
+        let mut expander = Expander::new(Flaky(&packed, 0));
+        let mut out = Vec::new();
+        let mut buf = [0; 5];
+        loop {
+            match expander.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => out.extend_from_slice(&buf[..n]),
+                Err(e) => assert!(matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::Interrupted)),
+            }
+        }
+        assert!(out == bs);
+    }
+
+    #[test]
+    fn truncated() {
+        let packed = compress(b"a fairly short string, a fairly short string");
+        for len in 0 .. packed.len() {
+            match expand(&packed[..len]) {
+                Err(Error::Truncated{offset}) => assert_eq!(offset, len),
+                other => panic!("expected truncation at {len}, got {other:?}"),
+            }
+            let e = expand_streaming(&packed[..len], 3).unwrap_err();
+            assert_eq!(e.kind(), std::io::ErrorKind::UnexpectedEof);
+        }
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn arbitrary_input(bs in proptest::collection::vec(proptest::num::u8::ANY, 0..4096)) {
+            let expanded = expand(&bs);
+            let streamed = expand_streaming(&bs, 13);
+            match (expanded, streamed) {
+                (Ok(a), Ok(b)) => proptest::prop_assert!(a == b),
+                (Err(a), Err(_)) => proptest::prop_assert_eq!(a.offset(), bs.len()),
+                (a, b) => proptest::prop_assert!(false, "disagree: {:?} vs {:?}", a, b),
+            }
+        }
+
+        #[test]
+        fn arbitrary_round_trip(bs in proptest::collection::vec(0u8..4, 0..20_000)) {
+            proptest::prop_assert!(expand(&compress(&bs)).unwrap() == bs);
+        }
+    }
+}
@@ -0,0 +1,253 @@
+//! `.prm` model files: a run of objects, each a header, its vertices and its primitives. Every
+//! primitive starts with a type and flags word; colours within a primitive sit on a 4-byte
+//! boundary relative to its start.
+
+use crate::reader::{self, Endian, Read, Reader, Skip};
+
+/// Colour as stored, with a spare fourth byte.
+pub type Rgbx = [u8; 4];
+
+#[derive(Debug, Clone)]
+pub struct Object {
+    pub name:  String,
+    pub pos:   [i32; 3],
+    pub verts: Vec<[i16; 3]>,
+    pub prims: Vec<Prim>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Prim {
+    Pad,
+    Poly(Poly),
+    Line {
+        verts:  [u16; 2],
+        colour: Rgbx,
+    },
+    Sprite {
+        /// Hangs down from its vertex, rather than standing on it
+        top:    bool,
+        vertex: u16,
+        size:   [u16; 2],
+        tex:    u16,
+        colour: Rgbx,
+    },
+    Spline {
+        /// Control point, position, control point
+        points: [[i32; 3]; 3],
+        colour: Rgbx,
+    },
+    DirLight {
+        /// Unit length is 4096
+        dir:    [i16; 3],
+        colour: Rgbx,
+    },
+    PointLight {
+        pos:     [i32; 3],
+        colour:  Rgbx,
+        falloff: [i16; 2],
+    },
+    SpotLight {
+        pos:     [i32; 3],
+        dir:     [i16; 3],
+        colour:  Rgbx,
+        falloff: [i16; 2],
+        /// Cone and spread angles; a full turn is 4096
+        cone:    [i16; 2],
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct Poly {
+    pub quad:    bool,
+    /// The fourth is only meaningful for quads
+    pub verts:   [u16; 4],
+    /// Texture index and per-vertex texel coordinates
+    pub tex:     Option<(u16, [[u8; 2]; 4])>,
+    /// Normal indices for lit polys; one, or one per vertex if smooth
+    pub normals: Vec<i16>,
+    /// One colour, or one per vertex if smooth
+    pub colours: Vec<Rgbx>,
+}
+
+pub fn load_prm(prm: &[u8], endian: Endian) -> Result<Vec<Object>, reader::Error> {
+    Reader::new(prm).with_endian(endian).read_all("Object")
+}
+
+crate::layout! {
+    struct ObjectHeader {
+        name:    [u8; 15],      //   0 ..  15
+        _pad0:   Skip<1>,       //  15 ..  16
+        n_verts: u16,           //  16 ..  18
+        _pad1:   Skip<14>,      //  18 ..  32
+        n_prims: u16,           //  32 ..  34
+        _pad2:   Skip<82>,      //  34 .. 116
+        pos:     [i32; 3],      // 116 .. 128
+        _pad3:   Skip<16>,      // 128 .. 144
+    }
+}
+
+impl Read for Object {
+    fn read(r: &mut Reader<'_>) -> Result<Self, reader::Error> {
+        let head: ObjectHeader = r.field("header")?;
+        let name = String::from_utf8_lossy(&head.name)
+            .trim()
+            .trim_end_matches(|ch: char| !ch.is_ascii_graphic())
+            .to_owned();
+
+        let verts = r.array::<[i16; 4]>("verts", head.n_verts as usize)?
+            .into_iter()
+            .map(|[x, y, z, _]| [x, y, z])
+            .collect();
+
+        let prims = r.array_with("prims", head.n_prims as usize, |r| read_prim(r, &name))?;
+
+        Ok(Object{name, pos: head.pos, verts, prims})
+    }
+}
+
+fn read_prim(r: &mut Reader<'_>, obj_name: &str) -> Result<Prim, reader::Error> {
+    let start = r.pos();
+    let ty: u16 = r.field("type")?;
+    let _flags: u16 = r.field("flags")?;
+
+    let align = |r: &mut Reader| r.skip((4 - (r.pos() - start) % 4) % 4);
+
+    let prim = match ty {
+        0 => {
+            r.skip(14)?;
+            Prim::Pad
+        }
+
+        1..=8 | 12..=19 => {
+            let lit = ty >= 12;
+            let bits = if lit {ty - 12} else {ty - 1};
+            let textured = bits & 1 != 0;
+            let quad     = bits & 2 != 0;
+            let smooth   = bits & 4 != 0;
+            let n_verts = if quad {4} else {3};
+            let n_smooth = if smooth {n_verts} else {1};
+
+            let mut verts = [0; 4];
+            for (i, v) in r.array::<u16>("verts", n_verts)?.into_iter().enumerate() {
+                verts[i] = v;
+            }
+
+            let normals = if lit {r.array("normals", n_smooth)?} else {Vec::new()};
+
+            let tex = if textured {
+                let tex: u16 = r.field("tex")?;
+                let _: Skip<4> = r.field("cba_tsb")?;
+                let mut uvs = [[0; 2]; 4];
+                for (i, uv) in r.array::<[u8; 2]>("uvs", n_verts)?.into_iter().enumerate() {
+                    uvs[i] = uv;
+                }
+                Some((tex, uvs))
+            }
+            else {
+                None
+            };
+
+            align(r)?;
+            let colours = r.array("colours", n_smooth)?;
+            Prim::Poly(Poly{quad, verts, tex, normals, colours})
+        }
+
+        9 => Prim::Line {
+            verts:  r.field("verts")?,
+            colour: r.field("colour")?,
+        },
+
+        10 | 11 => Prim::Sprite {
+            top:    ty == 10,
+            vertex: r.field("vertex")?,
+            size:   r.field("size")?,
+            tex:    r.field("tex")?,
+            colour: r.field("colour")?,
+        },
+
+        20 => {
+            let points: [[i32; 4]; 3] = r.field("points")?;
+            Prim::Spline {
+                points: points.map(|[x, y, z, _]| [x, y, z]),
+                colour: r.field("colour")?,
+            }
+        }
+
+        21 => {
+            let [x, y, z, _]: [i16; 4] = r.field("dir")?;
+            Prim::DirLight {
+                dir:    [x, y, z],
+                colour: r.field("colour")?,
+            }
+        }
+
+        22 => {
+            let [x, y, z, _]: [i32; 4] = r.field("pos")?;
+            Prim::PointLight {
+                pos:     [x, y, z],
+                colour:  r.field("colour")?,
+                falloff: r.field("falloff")?,
+            }
+        }
+
+        23 => {
+            let [x, y, z, _]: [i32; 4] = r.field("pos")?;
+            let [dx, dy, dz, _]: [i16; 4] = r.field("dir")?;
+            Prim::SpotLight {
+                pos:     [x, y, z],
+                dir:     [dx, dy, dz],
+                colour:  r.field("colour")?,
+                falloff: r.field("falloff")?,
+                cone:    r.field("cone")?,
+            }
+        }
+
+        _ => return Err(r.invalid(format!("object {obj_name:?}: unknown primitive type {ty:#x}"))),
+    };
+
+    Ok(prim)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn object(prims: &[u8], n_prims: u16) -> Vec<u8> {
+        let mut obj = vec![0; 144];
+        obj[..4].copy_from_slice(b"cube");
+        obj[16..18].copy_from_slice(&1u16.to_be_bytes());
+        obj[32..34].copy_from_slice(&n_prims.to_be_bytes());
+        obj[116..120].copy_from_slice(&100i32.to_be_bytes());
+        obj.extend([0, 1, 0, 2, 0, 3, 0, 0]);
+        obj.extend(prims);
+        obj
+    }
+
+    #[test]
+    fn prims() {
+        let mut prims = Vec::new();
+        // flat triangle: type, flags, 3 verts, pad, colour
+        prims.extend([0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 2, 3, 0]);
+        // directional light
+        prims.extend([0, 21, 0, 0, 0x10, 0, 0, 0, 0, 0, 0, 0, 9, 8, 7, 0]);
+
+        let objs = load_prm(&object(&prims, 2), Endian::Big).unwrap();
+        assert_eq!(objs.len(), 1);
+        assert_eq!(objs[0].name, "cube");
+        assert_eq!(objs[0].pos, [100, 0, 0]);
+        assert_eq!(objs[0].verts, [[1, 2, 3]]);
+        let Prim::Poly(poly) = &objs[0].prims[0] else { panic!() };
+        assert!(!poly.quad && poly.tex.is_none());
+        assert_eq!(poly.colours, [[1, 2, 3, 0]]);
+        let Prim::DirLight{dir, colour} = objs[0].prims[1] else { panic!() };
+        assert_eq!((dir, colour), ([0x1000, 0, 0], [9, 8, 7, 0]));
+    }
+
+    #[test]
+    fn unknown_prim() {
+        let e = load_prm(&object(&[0, 99, 0, 0], 1), Endian::Big).unwrap_err();
+        assert_eq!(e.offset(), 152);
+        assert_eq!(e.to_string(),
+            "+0x98: Object[0].prims[0]: object \"cube\": unknown primitive type 0x63");
+    }
+}
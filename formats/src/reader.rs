@@ -0,0 +1,334 @@
+//! Field-by-field reading of binary layouts, so that errors can say exactly where they went
+//! wrong, as in `track.trs +0x1f40: Section[52].face_n: needs 2 bytes, only 1 left`.
+//!
+//! Types implement [`Read`], usually by being declared with [`layout!`](crate::layout), which
+//! reads each field in turn and names it in any error. Multi-byte numbers are big-endian unless
+//! the reader is switched to little-endian.
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Endian {
+    #[default]
+    Big,
+    Little,
+}
+
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    endian: Endian,
+    /// What's being read, and where each part of that started
+    path: Vec<(Seg, usize)>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Seg {
+    Name(&'static str),
+    Field(&'static str),
+    Index(usize),
+}
+
+#[derive(Debug)]
+pub struct Error {
+    file: Option<String>,
+    offset: usize,
+    path: String,
+    kind: ErrorKind,
+}
+
+#[derive(Debug)]
+pub enum ErrorKind {
+    Underrun{need: usize, have: usize},
+    Invalid(String),
+}
+
+impl Error {
+    /// Names the file the error came from, unless it's already named.
+    pub fn in_file(mut self, name: impl Into<String>) -> Self {
+        self.file.get_or_insert_with(|| name.into());
+        self
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Field path, such as `Section[52].face_n`.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(file) = &self.file { write!(f, "{file} ")?; }
+        write!(f, "+{:#x}", self.offset)?;
+        if !self.path.is_empty() { write!(f, ": {}", self.path)?; }
+        write!(f, ": {}", self.kind)
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorKind::Underrun{need, have} => write!(f, "needs {need} bytes, only {have} left"),
+            ErrorKind::Invalid(msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl std::error::Error for Error { }
+
+pub trait InFile {
+    fn in_file(self, name: &str) -> Self;
+}
+
+impl<T> InFile for Result<T, Error> {
+    fn in_file(self, name: &str) -> Self {
+        self.map_err(|e| e.in_file(name))
+    }
+}
+
+pub trait Read: Sized {
+    fn read(r: &mut Reader<'_>) -> Result<Self, Error>;
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Reader{bytes, pos: 0, endian: Endian::Big, path: Vec::new()}
+    }
+
+    pub fn little(self) -> Self {
+        self.with_endian(Endian::Little)
+    }
+
+    pub fn with_endian(mut self, endian: Endian) -> Self {
+        self.endian = endian;
+        self
+    }
+
+    pub fn endian(&self) -> Endian {
+        self.endian
+    }
+
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.bytes.len() - self.pos
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    pub fn rest(&self) -> &'a [u8] {
+        &self.bytes[self.pos..]
+    }
+
+    pub fn bytes(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        let have = self.remaining();
+        if have < n {
+            return Err(self.error_at(self.pos, ErrorKind::Underrun{need: n, have}));
+        }
+        let bytes = &self.bytes[self.pos .. self.pos + n];
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    pub fn skip(&mut self, n: usize) -> Result<(), Error> {
+        self.bytes(n).map(drop)
+    }
+
+    pub fn read<T: Read>(&mut self) -> Result<T, Error> {
+        T::read(self)
+    }
+
+    /// Reads a named field of whatever's being read.
+    pub fn field<T: Read>(&mut self, name: &'static str) -> Result<T, Error> {
+        self.within(Seg::Field(name), T::read)
+    }
+
+    /// Runs `f` as reading a named field, so that its errors carry the field's name.
+    pub fn with<T>(&mut self, name: &'static str, f: impl FnOnce(&mut Self) -> Result<T, Error>)
+        -> Result<T, Error>
+    {
+        self.within(Seg::Field(name), f)
+    }
+
+    /// Reads `n` records, naming them `name[i]` in errors.
+    pub fn array<T: Read>(&mut self, name: &'static str, n: usize) -> Result<Vec<T>, Error> {
+        self.array_with(name, n, T::read)
+    }
+
+    /// Like `array`, reading each record with `f`.
+    pub fn array_with<T>(
+        &mut self,
+        name: &'static str,
+        n: usize,
+        mut f: impl FnMut(&mut Self) -> Result<T, Error>,
+    ) -> Result<Vec<T>, Error> {
+        self.within(Seg::Name(name), |r| {
+            (0..n).map(|i| r.within(Seg::Index(i), &mut f)).collect()
+        })
+    }
+
+    /// Reads records up to the end of the input; a partial record at the end is an error.
+    pub fn read_all<T: Read>(&mut self, name: &'static str) -> Result<Vec<T>, Error> {
+        self.within(Seg::Name(name), |r| {
+            let mut items = Vec::new();
+            while !r.is_empty() {
+                let i = items.len();
+                items.push(r.within(Seg::Index(i), T::read)?);
+            }
+            Ok(items)
+        })
+    }
+
+    /// An error about a bad value in whatever's being read, reported at its start.
+    pub fn invalid(&self, msg: impl fmt::Display) -> Error {
+        let at = self.path.last().map_or(self.pos, |&(_, at)| at);
+        self.error_at(at, ErrorKind::Invalid(msg.to_string()))
+    }
+
+    fn within<T>(&mut self, seg: Seg, f: impl FnOnce(&mut Self) -> Result<T, Error>)
+        -> Result<T, Error>
+    {
+        self.path.push((seg, self.pos));
+        let result = f(self);
+        self.path.pop();
+        result
+    }
+
+    fn error_at(&self, offset: usize, kind: ErrorKind) -> Error {
+        use fmt::Write as _;
+        let mut path = String::new();
+        for (seg, _) in &self.path {
+            match *seg {
+                Seg::Name(name) | Seg::Field(name) => {
+                    if !path.is_empty() { path.push('.'); }
+                    path.push_str(name);
+                }
+                Seg::Index(i) => { let _ = write!(path, "[{i}]"); }
+            }
+        }
+        Error{file: None, offset, path, kind}
+    }
+}
+
+macro_rules! impl_read_int {
+    ($($ty:ty),*) => {$(
+        impl Read for $ty {
+            fn read(r: &mut Reader<'_>) -> Result<Self, Error> {
+                let bytes = r.bytes(std::mem::size_of::<$ty>())?.try_into().unwrap();
+                Ok(match r.endian {
+                    Endian::Big    => <$ty>::from_be_bytes(bytes),
+                    Endian::Little => <$ty>::from_le_bytes(bytes),
+                })
+            }
+        }
+    )*}
+}
+
+impl_read_int!(u8, i8, u16, i16, u32, i32);
+
+impl<T: Read, const N: usize> Read for [T; N] {
+    fn read(r: &mut Reader<'_>) -> Result<Self, Error> {
+        std::array::try_from_fn(|i| r.within(Seg::Index(i), T::read))
+    }
+}
+
+/// `N` bytes of padding, or of fields nobody needs yet.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Skip<const N: usize>;
+
+impl<const N: usize> Read for Skip<N> {
+    fn read(r: &mut Reader<'_>) -> Result<Self, Error> {
+        r.skip(N).map(|_| Skip)
+    }
+}
+
+/// Declares structs along with [`Read`] impls that read their fields in order.
+///
+/// Padding in a public struct should be public too, or the struct can't be built outside the
+/// crate that declares it.
+///
+/// ```ignore
+/// formats::layout! {
+///     #[derive(Debug)]
+///     pub struct Vertex {
+///         pub xyz:  [i32; 3],
+///         pub _pad: Skip<4>,
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! layout {
+    ($(
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $( $(#[$fmeta:meta])* $fvis:vis $field:ident : $ty:ty ),* $(,)?
+        }
+    )*) => {$(
+        $(#[$meta])*
+        $vis struct $name {
+            $( $(#[$fmeta])* $fvis $field : $ty ),*
+        }
+
+        impl $crate::reader::Read for $name {
+            fn read(r: &mut $crate::reader::Reader<'_>) -> Result<Self, $crate::reader::Error> {
+                Ok($name {
+                    $( $field: r.field(stringify!($field))? ),*
+                })
+            }
+        }
+    )*}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    crate::layout! {
+        #[derive(Debug, PartialEq)]
+        struct Thing {
+            a: u16,
+            _pad: Skip<2>,
+            b: [i32; 2],
+        }
+    }
+
+    #[test]
+    fn layouts() {
+        let bytes = [0, 1, 9, 9, 0, 0, 0, 2, 0xff, 0xff, 0xff, 0xfd];
+        let thing: Thing = Reader::new(&bytes).read().unwrap();
+        assert_eq!(thing, Thing{a: 1, _pad: Skip, b: [2, -3]});
+
+        let thing: Thing = Reader::new(&bytes).little().read().unwrap();
+        assert_eq!(thing.a, 0x100);
+    }
+
+    #[test]
+    fn errors() {
+        let bytes = [0u8; 12 * 2 + 7];
+        let e = Reader::new(&bytes).read_all::<Thing>("Thing").in_file("things.bin").unwrap_err();
+        assert_eq!(e.offset(), 28);
+        assert_eq!(e.path(), "Thing[2].b[0]");
+        assert_eq!(e.to_string(), "things.bin +0x1c: Thing[2].b[0]: needs 4 bytes, only 3 left");
+
+        let mut r = Reader::new(&bytes);
+        r.skip(4).unwrap();
+        let e = r.with("magic", |r| {
+            let magic: u16 = r.read()?;
+            if magic != 0x10 { return Err(r.invalid(format!("bad magic {magic:#x}"))); }
+            Ok(magic)
+        });
+        assert_eq!(e.unwrap_err().to_string(), "+0x4: magic: bad magic 0x0");
+    }
+}
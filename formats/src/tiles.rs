@@ -0,0 +1,18 @@
+//! Road tile maps: `library.ttf` says which `library.cmp` fragments make up each road tile, at
+//! three levels of detail.
+
+use crate::reader::{self, Endian, Reader};
+
+crate::layout! {
+    /// Fragment indices for one road tile, row by row.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct TileMap {
+        pub hi: [[u16; 4]; 4],  //   0 .. 32
+        pub md: [[u16; 2]; 2],  //  32 .. 40
+        pub lo: [[u16; 1]; 1],  //  40 .. 42
+    }
+}
+
+pub fn load_ttf(ttf: &[u8], endian: Endian) -> Result<Vec<TileMap>, reader::Error> {
+    Reader::new(ttf).with_endian(endian).read_all("TileMap")
+}
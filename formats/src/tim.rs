@@ -0,0 +1,218 @@
+use {
+    anyhow::{Result as Anyhow, anyhow},
+    pixmap::{Color, Pixmap, Rgb555, Rgba},
+    std::collections::HashMap,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimDepth {
+    /// 16-entry CLUT
+    Indexed4,
+    /// 256-entry CLUT
+    Indexed8,
+    Direct15,
+}
+
+/// Encodes `image` as a TIM.
+///
+/// Colours are written as [`Rgb555::from_rgba`] has them: alpha 0 becomes the transparent word
+/// `0x0000`, black becomes `0x8000` so it isn't keyed out, and any other alpha below 0xff sets
+/// STP. `load_tim` reads these back as transparent and opaque, with STP in `Texture::stp`. Rows
+/// are padded with transparent pixels out to a whole number of 16-bit units, and indexed images
+/// are quantised down to fit their CLUT.
+pub fn encode_tim<Pixels>(image: &Pixmap<Pixels>, depth: TimDepth) -> Anyhow<Vec<u8>> where
+    Pixels: AsRef<[Rgba]>,
+{
+    let per_unit = match depth {
+        TimDepth::Indexed4 => 4,
+        TimDepth::Indexed8 => 2,
+        TimDepth::Direct15 => 1,
+    };
+
+    let units = image.wide().div_ceil(per_unit);
+    let wide = units * per_unit;
+    let high = image.high();
+    let dims = [units, high].map(u16::try_from);
+    let [Ok(units), Ok(high16)] = dims else {
+        return Err(anyhow!("{}x{high} image is too big for a tim", image.wide()));
+    };
+
+    let words = util::row_major(0..wide, 0..high)
+        .map(|(x, y)| image.get([x, y]).map_or(0x0000, |p| Rgb555::from_rgba(p).0))
+        .collect::<Vec<_>>();
+
+    let mut tim = Vec::new();
+    tim.extend_from_slice(&[0x10, 0x00, 0x00, 0x00]);
+
+    let data = match depth {
+        TimDepth::Direct15 => {
+            tim.extend_from_slice(&2u32.to_le_bytes());
+            words.iter().flat_map(|w| w.to_le_bytes()).collect::<Vec<_>>()
+        }
+
+        TimDepth::Indexed4 | TimDepth::Indexed8 => {
+            let four_bit = depth == TimDepth::Indexed4;
+            let pal_n = if four_bit {16} else {256};
+            let (clut, lookup) = quantise(&words, pal_n);
+
+            let pixel_type = if four_bit {0u32} else {1};
+            tim.extend_from_slice(&(pixel_type | 8).to_le_bytes());
+            push_block(&mut tim, [pal_n as u16, 1], clut.iter().flat_map(|w| w.to_le_bytes()));
+
+            let indices = words.iter().map(|w| lookup[w]);
+            if four_bit {
+                indices.array_chunks().map(|[lo, hi]| lo | hi << 4).collect()
+            }
+            else {
+                indices.collect()
+            }
+        }
+    };
+
+    push_block(&mut tim, [units, high16], data);
+    Ok(tim)
+}
+
+fn push_block(tim: &mut Vec<u8>, [w, h]: [u16; 2], data: impl IntoIterator<Item = u8>) {
+    let start = tim.len();
+    tim.extend_from_slice(&[0; 12]);
+    tim.extend(data);
+    let len = (tim.len() - start) as u32;
+    tim[start .. start+4].copy_from_slice(&len.to_le_bytes());
+    // vram x, y left at 0
+    tim[start+ 8 .. start+10].copy_from_slice(&w.to_le_bytes());
+    tim[start+10 .. start+12].copy_from_slice(&h.to_le_bytes());
+}
+
+/// Median-cut quantisation of colour words to a `pal_n` entry CLUT, with `0x0000` kept exact.
+fn quantise(words: &[u16], pal_n: usize) -> (Vec<u16>, HashMap<u16, u8>) {
+    let mut counts = HashMap::<u16, usize>::new();
+    for &w in words {
+        *counts.entry(w).or_default() += 1;
+    }
+
+    let mut clut = Vec::with_capacity(pal_n);
+    let mut lookup = HashMap::new();
+    if counts.remove(&0x0000).is_some() {
+        lookup.insert(0x0000, 0);
+        clut.push(0x0000);
+    }
+
+    let mut colours = counts.into_iter().collect::<Vec<_>>();
+    colours.sort_unstable();
+
+    let budget = pal_n - clut.len();
+    let mut boxes = vec![colours];
+    while boxes.len() < budget {
+        let widest = boxes.iter()
+            .enumerate()
+            .filter(|(_, bx)| bx.len() > 1)
+            .map(|(i, bx)| (i, widest_axis(bx)))
+            .max_by_key(|&(_, (_, range))| range);
+        let Some((i, (axis, _))) = widest else {break};
+
+        let mut bx = boxes.swap_remove(i);
+        bx.sort_unstable_by_key(|&(w, _)| axis_value(w, axis));
+        let total = bx.iter().map(|&(_, n)| n).sum::<usize>();
+        let mut acc = 0;
+        let split = bx.iter()
+            .position(|&(_, n)| {acc += n; acc * 2 >= total})
+            .unwrap()
+            .clamp(0, bx.len() - 2) + 1;
+        let upper = bx.split_off(split);
+        boxes.push(bx);
+        boxes.push(upper);
+    }
+
+    // empty when every pixel is transparent
+    for bx in boxes.into_iter().filter(|bx| !bx.is_empty()) {
+        let i = clut.len() as u8;
+        clut.push(average(&bx));
+        lookup.extend(bx.into_iter().map(|(w, _)| (w, i)));
+    }
+
+    clut.resize(pal_n, 0x0000);
+    (clut, lookup)
+}
+
+/// r, g, b, then STP, which is scaled so that mixed boxes get split on it first
+fn axis_value(w: u16, axis: usize) -> u16 {
+    if axis == 3 {(w >> 15) * 32} else {(w >> (axis * 5)) & 0x1f}
+}
+
+fn widest_axis(bx: &[(u16, usize)]) -> (usize, u16) {
+    (0..4)
+        .map(|axis| {
+            let values = bx.iter().map(|&(w, _)| axis_value(w, axis));
+            let range = values.clone().max().unwrap() - values.min().unwrap();
+            (axis, range)
+        })
+        .max_by_key(|&(_, range)| range)
+        .unwrap()
+}
+
+fn average(bx: &[(u16, usize)]) -> u16 {
+    let total = bx.iter().map(|&(_, n)| n).sum::<usize>();
+    let [r, g, b, stp] = [0, 1, 2, 3].map(|axis| {
+        let sum = bx.iter().map(|&(w, n)| axis_value(w, axis) as usize * n).sum::<usize>();
+        (sum + total / 2) / total
+    });
+    let stp = if stp >= 16 {0x8000} else {0};
+    let rgb = (r | g << 5 | b << 10) as u16;
+    if rgb == 0 {0x8000} else {rgb | stp}
+}
+
+#[cfg(test)]
+#[test]
+fn round_trip() {
+    use crate::{RawTim, load_tim_raw};
+
+    let image = Pixmap::new_from_fn(13, 7, |[x, y]| {
+        match (x + y) % 5 {
+            0 => Rgba::TRANSPARENT,
+            1 => Rgba::new(0xff, 0x00, 0x00, 0x80),
+            2 => Rgba::BLACK,
+            _ => Rgba::new((x * 19) as u8, (y * 36) as u8, 0x40, 0xff),
+        }
+    });
+
+    for depth in [TimDepth::Indexed4, TimDepth::Indexed8, TimDepth::Direct15] {
+        let raw = load_tim_raw(&encode_tim(&image, depth).unwrap()).unwrap();
+        assert!(raw.wide() >= image.wide());
+        assert_eq!(raw.high(), image.high());
+        let word_at = |xy: [i32; 2]| match (depth, &raw) {
+            (TimDepth::Indexed4, RawTim::Indexed(indexed)) => {
+                assert_eq!(indexed.palette().len(), 16);
+                indexed.get(xy).unwrap()
+            }
+            (TimDepth::Indexed8, RawTim::Indexed(indexed)) => {
+                assert_eq!(indexed.palette().len(), 256);
+                indexed.get(xy).unwrap()
+            }
+            (TimDepth::Direct15, RawTim::Direct15(words)) => words.get(xy).unwrap(),
+            _ => panic!("wrong kind of raw tim for {depth:?}"),
+        };
+
+        for (x, y) in util::row_major(0..image.wide(), 0..image.high()) {
+            let want = Rgb555::from_rgba(image.get([x, y]).unwrap());
+            let got = word_at([x, y]);
+            if depth == TimDepth::Indexed4 {
+                // too many colours for the clut, but transparency and STP survive
+                assert_eq!(got == Rgb555::TRANSPARENT, want == Rgb555::TRANSPARENT, "at {x},{y}");
+                assert_eq!(got.stp(), want.stp(), "stp at {x},{y} ({depth:?})");
+            }
+            else {
+                assert_eq!(got, want, "word at {x},{y} ({depth:?})");
+            }
+        }
+
+        assert_eq!(word_at([2, 0]), Rgb555(0x8000), "black ({depth:?})");
+
+        let clear = Pixmap::new(5, 3, Rgba::TRANSPARENT);
+        let raw = load_tim_raw(&encode_tim(&clear, depth).unwrap()).unwrap();
+        let tex = raw.to_texture();
+        for (x, y) in util::row_major(0..5, 0..3) {
+            assert_eq!(tex.image.get([x, y]), Some(Rgba::TRANSPARENT), "clear ({depth:?})");
+        }
+    }
+}
@@ -0,0 +1,140 @@
+//! Track geometry: `.trv` vertices, `.trf` faces and `.trs` sections, plus the `.vew` view lists
+//! the sections refer to. Each is a bare array, big-endian in the original game's data.
+
+use {
+    crate::reader::{self, Endian, Reader, Skip},
+    anyhow::{Result as Anyhow, anyhow, bail},
+};
+
+crate::layout! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Vertex {
+        pub xyz:    [i32; 3],       //   0 ..  12
+        pub _pad:   Skip<4>,        //  12 ..  16
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Face {
+        pub verts:  [u16; 4],       //   0 ..   8
+        pub normal: [i16; 3],       //   8 ..  14
+        pub tex:    u8,             //  14
+        pub flags:  u8,             //  15
+        pub colour: [u8; 3],        //  16 ..  19
+        pub _pad:   Skip<1>,        //  19 ..  20
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Section {
+        /// section index, or -1
+        pub junction: i32,              //    0 ..   4
+        pub prev:     i32,              //    4 ..   8
+        pub next:     i32,              //    8 ..  12
+        pub centre:   [i32; 3],         //   12 ..  24
+        pub version:  i16,              //   24 ..  26
+        pub _pad0:    Skip<2>,          //   26 ..  28
+        /// file-time pointer to the section's scenery objects
+        pub objs:     u32,              //   28 ..  32
+        pub objs_n:   i16,              //   32 ..  34
+        pub _pad1:    Skip<2>,          //   34 ..  36
+        /// where this section's view lists are in the `.vew`; see `view_list`
+        pub views:    [[u32; 3]; 5],    //   36 ..  96
+        pub view_ns:  [[i16; 3]; 5],    //   96 .. 126
+        pub high:     [i16; 4],         //  126 .. 134
+        pub med:      [i16; 4],         //  134 .. 142
+        pub face_st:  u16,              //  142 .. 144
+        pub face_n:   u16,              //  144 .. 146
+        pub r_global: i16,              //  146 .. 148
+        pub r_local:  i16,              //  148 .. 150
+        pub flags:    u16,              //  150 .. 152
+        pub sect_i:   i16,              //  152 .. 154
+        pub _pad2:    Skip<2>,          //  154 .. 156
+    }
+}
+
+impl Section {
+    pub const JUNCTION_START: u16 = 0x10;
+
+    pub fn junction(&self) -> Option<u32> {
+        u32::try_from(self.junction).ok()
+    }
+
+    pub fn faces(&self) -> std::ops::Range<usize> {
+        let face_st = self.face_st as usize;
+        face_st .. face_st + self.face_n as usize
+    }
+
+    /// Section indices in view list `[i][j]`, read out of the track's `.vew`.
+    ///
+    /// `views` is taken to hold byte offsets from the start of the `.vew`. That's a guess which
+    /// fits the lists being runs of `i16`s, but it hasn't been checked against the game, so
+    /// callers should be ready for lists that can't be read.
+    pub fn view_list<'v>(&self, vew: &'v [i16], i: usize, j: usize) -> Anyhow<&'v [i16]> {
+        let n = self.view_ns[i][j];
+        if n <= 0 {return Ok(&[])}
+        let offset = self.views[i][j] as usize;
+        if offset & 1 != 0 {bail!("view list [{i}][{j}] at odd offset {offset:#x}")}
+        let start = offset / 2;
+        vew.get(start .. start + n as usize)
+            .ok_or(anyhow!("view list [{i}][{j}] at {offset:#x} overruns .vew ({} entries)", n))
+    }
+
+    /// Every section listed in any of this section's view lists, sorted and deduplicated. Lists
+    /// that can't be read are left out, and handed to `bad`.
+    pub fn visible_sections(&self, vew: &[i16], mut bad: impl FnMut(anyhow::Error)) -> Vec<u16> {
+        let mut visible = Vec::new();
+        for (j, i) in util::row_major(0..3, 0..5) {
+            match self.view_list(vew, i, j) {
+                Ok(list) => visible.extend(list.iter().filter_map(|&si| u16::try_from(si).ok())),
+                Err(e) => bad(e),
+            }
+        }
+        visible.sort_unstable();
+        visible.dedup();
+        visible
+    }
+}
+
+pub fn load_trv(trv: &[u8], endian: Endian) -> Result<Vec<Vertex>, reader::Error> {
+    Reader::new(trv).with_endian(endian).read_all("Vertex")
+}
+
+pub fn load_trf(trf: &[u8], endian: Endian) -> Result<Vec<Face>, reader::Error> {
+    Reader::new(trf).with_endian(endian).read_all("Face")
+}
+
+pub fn load_trs(trs: &[u8], endian: Endian) -> Result<Vec<Section>, reader::Error> {
+    Reader::new(trs).with_endian(endian).read_all("Section")
+}
+
+pub fn load_vew(vew: &[u8], endian: Endian) -> Result<Vec<i16>, reader::Error> {
+    Reader::new(vew).with_endian(endian).read_all("vew")
+}
+
+#[cfg(test)]
+#[test]
+fn view_lists() {
+    let vew = [3, 4, 5, 7, -1, 1];
+
+    // one section with every list empty but those set here
+    let mut trs = vec![0; 156];
+    let mut set = |i: usize, j: usize, offset: u32, n: i16| {
+        let k = i * 3 + j;
+        trs[36 + k * 4 .. 40 + k * 4].copy_from_slice(&offset.to_be_bytes());
+        trs[96 + k * 2 .. 98 + k * 2].copy_from_slice(&n.to_be_bytes());
+    };
+    set(0, 0, 0, 3);
+    set(1, 2, 6, 2);
+    set(2, 1, 3, 1);
+    set(4, 0, 8, 5);
+    let [sect] = load_trs(&trs, Endian::Big).unwrap()[..] else {panic!()};
+
+    assert_eq!(sect.view_list(&vew, 0, 0).unwrap(), [3, 4, 5]);
+    assert_eq!(sect.view_list(&vew, 1, 2).unwrap(), [7, -1]);
+    assert!(sect.view_list(&vew, 3, 1).unwrap().is_empty());
+    assert!(sect.view_list(&vew, 2, 1).is_err());
+    assert!(sect.view_list(&vew, 4, 0).is_err());
+
+    let mut bad = 0;
+    assert_eq!(sect.visible_sections(&vew, |_| bad += 1), [3, 4, 5, 7]);
+    assert_eq!(bad, 2);
+}
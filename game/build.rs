@@ -21,6 +21,7 @@ fn main() {
     let config = bundle::bundler::Config {
         wipeout_dir: wipeout_dir.into(),
         out_path: bundle_path,
+        version: bundle::bundler::GameVersion::Wipeout,
     };
     bundle::bundler::make_bundle(config).unwrap();
 }
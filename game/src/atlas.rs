@@ -19,27 +19,46 @@ const ALIGN: i32 = 1 << N_REDUCTIONS;
 impl Atlas {
     pub fn build(gl: &Gl, iset: &bundle::ArchivedImageSet, label: &str) -> Anyhow<Atlas> {
         let mut pixmaps = {
-            let mut qoi_state = qoit::State::new();
-            let mut input = &iset.qoi_stream[..];
             log::debug!(target: "atlas", "decoding {} textures", iset.sizes.len());
-            let pixmaps = iset.sizes.iter().copied().enumerate()
-                .map(|(i, (w, h))| {
+            let mut pixels = iset.sizes.iter()
+                .map(|&(w, h)| vec![Rgba::TRANSPARENT; w as usize * h as usize])
+                .collect::<Vec<_>>();
+
+            let ends = iset.offsets.iter().skip(1).map(|&o| o as usize)
+                .chain([iset.qoi_stream.len()]);
+            let mut jobs = iset.offsets.iter().zip(ends).zip(&mut pixels)
+                .map(|((&start, end), pixels)| (
+                    &iset.qoi_stream[start as usize .. end],
+                    bytemuck::cast_slice_mut(&mut pixels[..]),
+                ))
+                .collect::<Vec<_>>();
+            jobs.sort_by_key(|(_, output)| std::cmp::Reverse(output.len()));
+            match iset.coding {
+                bundle::ArchivedImageCoding::Qoi    => qoit::decode_parallel(&mut jobs)?,
+                bundle::ArchivedImageCoding::QoiPsx => qoit::psx::decode_parallel(&mut jobs)?,
+            }
+
+            let mut stp_bits = iset.stp.iter()
+                .flat_map(|&byte| (0..8).map(move |i| byte >> i & 1 != 0));
+            for pixels in &mut pixels {
+                for (pixel, stp) in pixels.iter_mut().zip(stp_bits.by_ref()) {
+                    *pixel = blend_class(*pixel, stp);
+                }
+            }
+
+            pixels.into_iter().zip(iset.sizes.iter())
+                .enumerate()
+                .map(|(i, (pixels, &(w, h)))| {
                     log::trace!(target: "atlas", "texture {i} size: {w} {h}");
-                    let mut pixels = Vec::new();
-                    pixels.resize(w as usize * h as usize, Rgba::TRANSPARENT);
-                    input = qoi_state.decode_some(bytemuck::cast_slice_mut(&mut pixels), input)?;
-                    //log::debug!(target: "atlas", "next 16 bytes: {:x?}", &input[..16.min(input.len())]);
-                    let pm = Pixmap::new_from_pixels(pixels, 0, 1, w as i32, h as i32).unwrap();
-                    Ok(pm)
+                    Pixmap::new_from_pixels(pixels, 0, 1, w as i32, h as i32)
+                        .ok_or_else(|| anyhow::anyhow!("texture {i} has the wrong size"))
                 })
-                .collect::<Anyhow<Vec<_>>>()?;
-            //input.strip_prefix(&[0,0,0,0,0,0,0,1]).unwrap();
-            pixmaps
+                .collect::<Anyhow<Vec<_>>>()?
         };
 
         for (i, pm) in pixmaps.iter().enumerate() {
-            image::save_buffer(
-                format!("debug-out/iset-{label}-{i}.tga"),
+            qoit::codec::save_buffer(
+                format!("debug-out/iset-{label}-{i}.qoi"),
                 bytemuck::cast_slice(pm.try_as_slice().unwrap()),
                 pm.wide() as u32,
                 pm.high() as u32,
@@ -65,16 +84,10 @@ impl Atlas {
             std::cmp::Reverse((w.max(h) << 16) | w.min(h))
         );
 
-        let mut packer = RectPacker::new([1536; 2]);
-        let mut allocs = rects.into_iter()
-            .map(|(i, dims)| {
-                let rect = packer
-                    .pack_now(dims).unwrap()
-                    .corners().into();
-                (i, rect)
-            })
-            .collect::<Vec<(usize, uv::IVec4)>>();
-        allocs.sort_unstable_by_key(|&(i, _)| i);
+        // as small as will hold them, since road sets grow with every detail level
+        let allocs = (1 ..= 8).map(|n| n * 512)
+            .find_map(|side| pack(&rects, side))
+            .ok_or_else(|| anyhow::anyhow!("{label} textures don't fit in a 4096² atlas"))?;
 
         let dims = allocs.iter().copied()
             .map(|(_, rect)| uv::IVec2::new(rect.z, rect.w))
@@ -108,8 +121,8 @@ impl Atlas {
             })
             .collect::<Vec<_>>();
 
-        /*image::save_buffer(
-            "debug-out/road-atlas.tga",
+        /*qoit::codec::save_buffer(
+            "debug-out/road-atlas.qoi",
             bytemuck::cast_slice(image.try_as_slice().unwrap()),
             image.wide() as u32,
             image.high() as u32,
@@ -138,6 +151,31 @@ impl Atlas {
     }
 }
 
+/// Packs `rects` into a `side`² square, in index order, or `None` if they don't fit.
+fn pack(rects: &[(usize, [i32; 2])], side: i32) -> Option<Vec<(usize, uv::IVec4)>> {
+    let mut packer = RectPacker::new([side; 2]);
+    let mut allocs = rects.iter()
+        .map(|&(i, dims)| {
+            let rect = packer.pack_now(dims).into_iter().next()?;
+            Some((i, rect.corners().into()))
+        })
+        .collect::<Option<Vec<(usize, uv::IVec4)>>>()?;
+    allocs.sort_unstable_by_key(|&(i, _)| i);
+    Some(allocs)
+}
+
+/// Sets `texel`'s alpha to 0 for transparent, 0x80 for semi-transparent or 0xff for opaque,
+/// which `basic-f.glsl` acts on. Until primitives' ABR bits are carried through, every STP texel
+/// but black is taken as semi-transparent; `0x8000` is how the PSX spells opaque black.
+fn blend_class(Rgba([r, g, b, a]): Rgba, stp: bool) -> Rgba {
+    let a = match a {
+        0                               => 0x00,
+        _ if stp && [r, g, b] != [0; 3] => 0x80,
+        _                               => 0xff,
+    };
+    Rgba([r, g, b, a])
+}
+
 fn checkerboard(w: i32, h: i32, log2_pitch: i32, a: Rgba, b: Rgba) -> Pixmap<Vec<Rgba>> {
     Pixmap::new_from_fn(
         w, h, 
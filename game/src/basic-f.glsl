@@ -14,7 +14,10 @@ out vec4 frag;
 void main() {
     vec4 texel = texture(tex, v_uv);
     //vec4 texel = vec4(1,1,1,1);
-    if(alpha_test && texel.a < 0.5) discard;
+    // alpha is 0 for transparent texels and 0.5 for semi-transparent ones, which are stippled
+    // for want of sorted blending
+    bool stipple = ((int(gl_FragCoord.x) ^ int(gl_FragCoord.y)) & 1) == 0;
+    if(alpha_test && (texel.a < 0.25 || (texel.a < 0.75 && stipple))) discard;
     vec3 final = v_rgb * texel.rgb;
     frag = vec4(final * final, 1);
 }
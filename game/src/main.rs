@@ -85,7 +85,7 @@ fn main() {
     let road_mesh = {
         log::debug!("loading {track_name}: road");
         let atlas = atlas::Atlas::build(&display, &track.road_iset, "road").unwrap();
-        road::RoadMesh::build(&display, &track.road_model, atlas)
+        road::RoadMesh::build(&display, &track.road_model, &track.graph, &track.road_iset, atlas)
     };
 
     let scenery = {
@@ -249,7 +249,8 @@ fn main() {
                         );
                     }
 
-                    road_mesh.draw(gl, &shader);
+                    let px_per_unit = h as f32 / (2. * (VFOV_DEG.to_radians() * 0.5).tan());
+                    road_mesh.draw(gl, &shader, cam_xform.translation, px_per_unit);
 
                     gl.Disable(gl::DEPTH_TEST);
                     shader.select(gl, ui_to_clip);
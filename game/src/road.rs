@@ -4,27 +4,48 @@ use {
         render,
         atlas::Atlas,
     },
+    ultraviolet as uv,
     util::unorm::*,
 };
 
 pub struct RoadMesh {
     tex: GLuint,
     vao: GLuint,
-    n_idxs: u32,
+    n_faces: u32,
+    /// World units per texel at each detail level
+    texel_spans: Vec<f32>,
+    sections: Vec<Section>,
+    /// Runs of faces that no section claims, as `[first, count]`
+    loose: Vec<[u32; 2]>,
+}
+
+struct Section {
+    center: uv::Vec3,
+    faces: [u32; 2],
 }
 
 impl RoadMesh {
-    pub fn build(gl: &Gl, model: &bundle::ArchivedRoadModel, atlas: Atlas) -> Self {
+    /// Builds one copy of the road per texture detail level in `iset`, so that each section
+    /// can be drawn with whichever suits its distance from the eye.
+    pub fn build(
+        gl: &Gl,
+        model: &bundle::ArchivedRoadModel,
+        graph: &[bundle::ArchivedTrackNode],
+        iset: &bundle::ArchivedImageSet,
+        atlas: Atlas,
+    ) -> Self {
         let n_faces = model.f_verts.len();
+        let lods = iset.lods.max(1) as usize;
+        let tiles_per_lod = iset.sizes.len() / lods;
 
-        let verts = (0 .. n_faces)
-            .flat_map(|face_i| {
+        let verts = util::row_major(0 .. n_faces, 0 .. lods)
+            .flat_map(|(face_i, lod)| {
                 let verts = model.f_verts[face_i];
-                let tex   = model.f_tex  [face_i];
+                let tex   = model.f_tex  [face_i] as usize + lod * tiles_per_lod;
                 let flags = model.f_flags[face_i];
                 let rgb   = model.f_rgb  [face_i];
 
-                let uvs: [f32; 4] = atlas.lookup_rect(tex as usize).into();
+                let uvs: [f32; 4] = atlas.lookup_rect(tex).into();
                 let [u0, v0, u1, v1]: [un16; 4] = uvs.map(un16::new);
                 let uvs = [[u1, v0], [u0, v0], [u0, v1], [u1, v1]];
                 let uvis =
@@ -40,10 +61,9 @@ impl RoadMesh {
             })
             .collect::<Vec<_>>();
 
-        let idxs = (0 .. n_faces as u32)
+        let idxs = (0 .. (n_faces * lods) as u32)
             .flat_map(|face_i| [0, 1, 2, 0, 2, 3].map(|i| i + face_i * 4))
             .collect::<Vec<_>>();
-        let n_idxs = idxs.len() as u32;
 
         let vao = render::make_arrays(
             gl,
@@ -57,21 +77,74 @@ impl RoadMesh {
 
         let tex = atlas.into_texture();
 
-        RoadMesh{tex, vao, n_idxs}
+        // each face is covered by one tile, so a level's texels span the average face divided
+        // by that level's tile width
+        let face_span = (0 .. n_faces)
+            .map(|face_i| {
+                let xyzs = model.f_verts[face_i].map(|vi| uv::Vec3::from(model.verts[vi as usize]));
+                (0 .. 4).map(|i| (xyzs[(i + 1) % 4] - xyzs[i]).mag()).sum::<f32>() / 4.
+            })
+            .sum::<f32>() / n_faces.max(1) as f32;
+        let texel_spans = (0 .. lods)
+            .map(|lod| {
+                let texels = iset.sizes.get(lod * tiles_per_lod).map_or(1, |&(w, _)| w.max(1));
+                face_span / texels as f32
+            })
+            .collect();
+
+        let sections = graph.iter()
+            .map(|node| Section{center: node.center.into(), faces: node.faces})
+            .collect();
+
+        let mut claimed = vec![false; n_faces];
+        for node in graph {
+            let [face_0, face_n] = node.faces;
+            claimed.iter_mut().skip(face_0 as usize).take(face_n as usize).for_each(|c| *c = true);
+        }
+        let mut loose = Vec::<[u32; 2]>::new();
+        for face_i in (0 .. n_faces as u32).filter(|&i| !claimed[i as usize]) {
+            match loose.last_mut() {
+                Some([face_0, face_n]) if *face_0 + *face_n == face_i => *face_n += 1,
+                _ => loose.push([face_i, 1]),
+            }
+        }
+        if !loose.is_empty() {
+            log::debug!("{} runs of road faces belong to no section", loose.len());
+        }
+
+        RoadMesh{tex, vao, n_faces: n_faces as u32, texel_spans, sections, loose}
     }
 
-    pub fn draw(&self, gl: &Gl, shader: &render::BasicShader) {
+    /// Draws each section with the least detailed textures whose texels come out no bigger
+    /// than a pixel. `px_per_unit` is how many pixels tall something one unit tall and one unit
+    /// from the eye is drawn. Faces that no section claims are drawn in full detail.
+    pub fn draw(&self, gl: &Gl, shader: &render::BasicShader, eye: uv::Vec3, px_per_unit: f32) {
         shader.setup(gl, |params| params.3 = true);
         unsafe {
             gl.BindVertexArray(self.vao);
             gl.BindTexture(gl::TEXTURE_2D, self.tex);
+        }
+        for sect in &self.sections {
+            let dist = (sect.center - eye).mag();
+            let lod = self.texel_spans.iter()
+                .rposition(|&span| span * px_per_unit <= dist)
+                .unwrap_or(0);
+            self.draw_faces(gl, lod as u32, sect.faces);
+        }
+        for &faces in &self.loose {
+            self.draw_faces(gl, 0, faces);
+        }
+    }
+
+    fn draw_faces(&self, gl: &Gl, lod: u32, [face_0, face_n]: [u32; 2]) {
+        let first = (lod * self.n_faces + face_0) as usize * 6;
+        unsafe {
             gl.DrawElements(
                 gl::TRIANGLES,
-                self.n_idxs as _,
+                face_n as i32 * 6,
                 gl::UNSIGNED_INT,
-                std::ptr::null(),
+                (first * std::mem::size_of::<u32>()) as *const _,
             );
         }
     }
 }
-
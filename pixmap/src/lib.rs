@@ -1,8 +1,25 @@
 #![feature(int_roundings)]
 
-use bytemuck as bm;
+use {
+    bytemuck as bm,
+    std::marker::PhantomData,
+};
+
+/// Anything a [`Pixmap`] can hold
+pub trait Pixel: Copy + 'static { }
+
+/// Pixels that stand for a colour by themselves, and so convert to and from any other
+pub trait Color: Pixel {
+    fn to_rgba(self) -> Rgba;
+    /// The nearest this type has to `rgba`
+    fn from_rgba(rgba: Rgba) -> Self;
+
+    fn convert<Q: Color>(self) -> Q {
+        Q::from_rgba(self.to_rgba())
+    }
+}
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash, bm::Pod, bm::Zeroable)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, bm::Pod, bm::Zeroable)]
 #[repr(C, align(4))]
 pub struct Rgba(pub [u8; 4]);
 
@@ -20,6 +37,102 @@ impl From<[u8; 4]> for Rgba {
     fn from(rgba: [u8; 4]) -> Self { Rgba(rgba) }
 }
 
+impl Pixel for Rgba { }
+
+impl Color for Rgba {
+    fn to_rgba(self) -> Rgba { self }
+    fn from_rgba(rgba: Rgba) -> Self { rgba }
+}
+
+/// Opaque colour
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, bm::Pod, bm::Zeroable)]
+#[repr(C)]
+pub struct Rgb(pub [u8; 3]);
+
+impl From<[u8; 3]> for Rgb {
+    fn from(rgb: [u8; 3]) -> Self { Rgb(rgb) }
+}
+
+impl Pixel for Rgb { }
+
+/// Alpha is dropped.
+impl Color for Rgb {
+    fn to_rgba(self) -> Rgba {
+        let Rgb([r, g, b]) = self;
+        Rgba([r, g, b, 0xff])
+    }
+
+    fn from_rgba(Rgba([r, g, b, _]): Rgba) -> Self {
+        Rgb([r, g, b])
+    }
+}
+
+/// Opaque grey
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, bm::Pod, bm::Zeroable)]
+#[repr(transparent)]
+pub struct Luma(pub u8);
+
+impl Pixel for Luma { }
+
+/// Colour goes by Rec. 601 luma, and alpha is dropped.
+impl Color for Luma {
+    fn to_rgba(self) -> Rgba {
+        let Luma(y) = self;
+        Rgba([y, y, y, 0xff])
+    }
+
+    fn from_rgba(Rgba([r, g, b, _]): Rgba) -> Self {
+        let y = (r as u32 * 299 + g as u32 * 587 + b as u32 * 114 + 500) / 1000;
+        Luma(y as u8)
+    }
+}
+
+/// A PSX colour word: 5 bits each of red, green and blue, then the STP bit.
+///
+/// As the PSX draws them, `0x0000` is transparent, and any other word with STP set is
+/// semi-transparent; as RGBA, those have alpha 0 and 0x80, and everything else 0xff. Opaque
+/// black has no word of its own, so it comes out as `0x8000`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, bm::Pod, bm::Zeroable)]
+#[repr(transparent)]
+pub struct Rgb555(pub u16);
+
+impl Rgb555 {
+    pub const TRANSPARENT: Rgb555 = Rgb555(0x0000);
+    pub const STP: u16 = 0x8000;
+
+    pub fn stp(self) -> bool {
+        self.0 & Self::STP != 0
+    }
+}
+
+impl From<u16> for Rgb555 {
+    fn from(word: u16) -> Self { Rgb555(word) }
+}
+
+impl Pixel for Rgb555 { }
+
+impl Color for Rgb555 {
+    fn to_rgba(self) -> Rgba {
+        if self == Self::TRANSPARENT {return Rgba::TRANSPARENT}
+        let [r, g, b] = [0, 5, 10].map(|shift| ((self.0 >> shift & 0x1f) * 255 / 31) as u8);
+        Rgba([r, g, b, if self.stp() {0x80} else {0xff}])
+    }
+
+    fn from_rgba(Rgba([r, g, b, a]): Rgba) -> Self {
+        if a == 0 {return Self::TRANSPARENT}
+        let [r, g, b] = [r, g, b].map(|y| ((y as u32 * 31 + 127) / 255) as u16);
+        let rgb = r | g << 5 | b << 10;
+        Rgb555(if a != 0xff || rgb == 0 {rgb | Self::STP} else {rgb})
+    }
+}
+
+/// An entry in a palette; see [`Indexed`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, bm::Pod, bm::Zeroable)]
+#[repr(transparent)]
+pub struct Index(pub u8);
+
+impl Pixel for Index { }
+
 mod meta {
     #[derive(Debug, Clone, Copy)]
     pub struct Meta {
@@ -74,70 +187,65 @@ mod meta {
 
 use meta::Meta;
 
-pub struct Pixmap<Pixels> {
+pub struct Pixmap<Pixels, P = Rgba> {
     pixels: Pixels,
     meta: Meta,
+    pixel: PhantomData<P>,
 }
 
-impl<Pixels> Pixmap<Pixels> {
+impl<Pixels, P> Pixmap<Pixels, P> {
     pub fn wide(&self) -> i32 { self.meta.wide().try_into().unwrap() }
     pub fn high(&self) -> i32 { self.meta.high().try_into().unwrap() }
 }
 
-impl Pixmap<Vec<Rgba>> {
-    pub fn new(wide: i32, high: i32, fill: Rgba) -> Self {
+impl<P: Pixel> Pixmap<Vec<P>, P> {
+    pub fn new(wide: i32, high: i32, fill: P) -> Self {
         let meta = Meta::try_new(0, 1, wide, high).unwrap();
-
-        let pixels = {
-            let len = meta.wide() * meta.high();
-            let mut pixels = Vec::new();
-            pixels.resize(len, fill);
-            pixels
-        };
-
-        Self{pixels: pixels.into(), meta}
+        let pixels = vec![fill; meta.wide() * meta.high()];
+        Self{pixels, meta, pixel: PhantomData}
     }
 
-    pub fn new_from_fn(wide: i32, high: i32, mut f: impl FnMut([i32; 2]) -> Rgba) -> Self {
-        let mut pm = Self::new(wide, high, Rgba::TRANSPARENT);
-        for (x, y) in iter_2d(0..wide, 0..high) {
-            let xy = [x, y];
-            pm.put(xy, f(xy));
-        }
-        pm
+    pub fn new_from_fn(wide: i32, high: i32, f: impl FnMut([i32; 2]) -> P) -> Self {
+        let meta = Meta::try_new(0, 1, wide, high).unwrap();
+        let pixels = iter_2d(0..wide, 0..high)
+            .map(|(x, y)| [x, y])
+            .map(f)
+            .collect();
+        Self{pixels, meta, pixel: PhantomData}
     }
 }
 
-impl<Pixels> Pixmap<Pixels> where Pixels: AsRef<[Rgba]> {
+impl<Pixels, P: Pixel> Pixmap<Pixels, P> where Pixels: AsRef<[P]> {
     pub fn new_from_pixels(pixels: Pixels, offset: i32, pitch: i32, wide: i32, high: i32)
         -> Option<Self>
     {
         let meta = Meta::try_new(offset, pitch, wide, high)?;
         meta.validate(pixels.as_ref().len())?;
-        Some(Self{pixels, meta})
+        Some(Self{pixels, meta, pixel: PhantomData})
     }
 
-    pub fn borrow(&self) -> Pixmap<&[Rgba]> {
+    pub fn borrow(&self) -> Pixmap<&[P], P> {
         Pixmap {
             pixels: self.pixels.as_ref(),
             meta: self.meta,
+            pixel: PhantomData,
         }
     }
 }
 
-impl<Pixels> Pixmap<Pixels> where Pixels: AsRef<[Rgba]> {
-    pub fn slice(&self, rect: impl Into<[i32; 4]>) -> Option<Pixmap<&[Rgba]>> {
+impl<Pixels, P: Pixel> Pixmap<Pixels, P> where Pixels: AsRef<[P]> {
+    pub fn slice(&self, rect: impl Into<[i32; 4]>) -> Option<Pixmap<&[P], P>> {
         let meta = self.meta.slice(rect.into())?;
         let pixels = self.pixels.as_ref();
-        Pixmap{pixels, meta}.validate()
+        Pixmap{pixels, meta, pixel: PhantomData}.validate()
     }
 }
 
-impl<Pixels> Pixmap<Pixels> where Pixels: AsMut<[Rgba]> + AsRef<[Rgba]> {
-    pub fn slice_mut(&mut self, rect: impl Into<[i32; 4]>) -> Option<Pixmap<&mut [Rgba]>> {
+impl<Pixels, P: Pixel> Pixmap<Pixels, P> where Pixels: AsMut<[P]> + AsRef<[P]> {
+    pub fn slice_mut(&mut self, rect: impl Into<[i32; 4]>) -> Option<Pixmap<&mut [P], P>> {
         let meta = self.meta.slice(rect.into())?;
         let pixels = self.pixels.as_mut();
-        Pixmap{pixels, meta}.validate()
+        Pixmap{pixels, meta, pixel: PhantomData}.validate()
     }
 }
 
@@ -150,50 +258,47 @@ impl<'a> Pixmap<&'a [Rgba]> {
     }
 }
 
-impl<Pixels> Pixmap<Pixels> {
-}
-
-impl<Pixels> Pixmap<Pixels> where Pixels: AsRef<[Rgba]> {
-    fn pixels(&self) -> &[Rgba] {
+impl<Pixels, P: Pixel> Pixmap<Pixels, P> where Pixels: AsRef<[P]> {
+    fn pixels(&self) -> &[P] {
         self.pixels.as_ref()
     }
 }
 
-impl<Pixels> Pixmap<Pixels> where Pixels: AsMut<[Rgba]> + AsRef<[Rgba]> {
-    fn pixels_mut(&mut self) -> &mut [Rgba] {
+impl<Pixels, P: Pixel> Pixmap<Pixels, P> where Pixels: AsMut<[P]> + AsRef<[P]> {
+    fn pixels_mut(&mut self) -> &mut [P] {
         self.pixels.as_mut()
     }
 }
 
-impl<Pixels> Pixmap<Pixels> where Pixels: AsRef<[Rgba]> {
+impl<Pixels, P: Pixel> Pixmap<Pixels, P> where Pixels: AsRef<[P]> {
     fn validate(self) -> Option<Self> {
         let len = self.pixels().len();
         self.meta.validate(len)?;
         Some(self)
     }
 
-    pub fn get(&self, at: impl Into<[i32; 2]>) -> Option<Rgba> {
+    pub fn get(&self, at: impl Into<[i32; 2]>) -> Option<P> {
         let index = self.meta.index(at.into())?;
         Some(self.pixels()[index])
     }
 }
 
-impl<Pixels> Pixmap<Pixels> where Pixels: AsMut<[Rgba]> + AsRef<[Rgba]> {
-    pub fn put(&mut self, at: impl Into<[i32; 2]>, p: impl Into<Rgba>) {
+impl<Pixels, P: Pixel> Pixmap<Pixels, P> where Pixels: AsMut<[P]> + AsRef<[P]> {
+    pub fn put(&mut self, at: impl Into<[i32; 2]>, p: impl Into<P>) {
         let index = self.meta.index(at.into()).unwrap();
         self.pixels_mut()[index] = p.into();
     }
 }
 
-impl<Pixels> Pixmap<Pixels> where Pixels: AsRef<[Rgba]> {
-    pub fn try_as_slice(&self) -> Option<&[Rgba]> {
+impl<Pixels, P: Pixel> Pixmap<Pixels, P> where Pixels: AsRef<[P]> {
+    pub fn try_as_slice(&self) -> Option<&[P]> {
         self.meta.is_contiguous().then(|| self.pixels())
     }
 }
 
-impl<Pixels> Pixmap<Pixels> where Pixels: AsMut<[Rgba]> + AsRef<[Rgba]> {
-    pub fn copy_from<Others> (&mut self, at: impl Into<[i32; 2]>, src: &Pixmap<Others>) where
-        Others: AsRef<[Rgba]>,
+impl<Pixels, P: Pixel> Pixmap<Pixels, P> where Pixels: AsMut<[P]> + AsRef<[P]> {
+    pub fn copy_from<Others> (&mut self, at: impl Into<[i32; 2]>, src: &Pixmap<Others, P>) where
+        Others: AsRef<[P]>,
     {
         let [dx0, dy0] = at.into();
         for (sx, sy) in iter_2d(0..src.wide(), 0..src.high()) {
@@ -204,6 +309,47 @@ impl<Pixels> Pixmap<Pixels> where Pixels: AsMut<[Rgba]> + AsRef<[Rgba]> {
     }
 }
 
+impl<Pixels, P: Color> Pixmap<Pixels, P> where Pixels: AsRef<[P]> {
+    /// A copy with every pixel converted to `Q`
+    pub fn convert<Q: Color>(&self) -> Pixmap<Vec<Q>, Q> {
+        Pixmap::new_from_fn(self.wide(), self.high(), |xy| self.get(xy).unwrap().convert())
+    }
+}
+
+/// An image of palette indices, with the palette
+pub struct Indexed<P = Rgba> {
+    indices: Pixmap<Vec<Index>, Index>,
+    palette: Vec<P>,
+}
+
+impl<P: Color> Indexed<P> {
+    /// `None` if any index is past the end of `palette`.
+    pub fn new(indices: Pixmap<Vec<Index>, Index>, palette: Vec<P>) -> Option<Self> {
+        iter_2d(0..indices.wide(), 0..indices.high())
+            .all(|(x, y)| (indices.get([x, y]).unwrap().0 as usize) < palette.len())
+            .then_some(Indexed{indices, palette})
+    }
+
+    pub fn wide(&self) -> i32 { self.indices.wide() }
+    pub fn high(&self) -> i32 { self.indices.high() }
+
+    pub fn indices(&self) -> &Pixmap<Vec<Index>, Index> { &self.indices }
+    pub fn palette(&self) -> &[P] { &self.palette }
+
+    pub fn get(&self, at: impl Into<[i32; 2]>) -> Option<P> {
+        let Index(i) = self.indices.get(at)?;
+        Some(self.palette[i as usize])
+    }
+
+    /// The image with every index looked up, as `Q`s
+    pub fn to_pixmap<Q: Color>(&self) -> Pixmap<Vec<Q>, Q> {
+        let palette = self.palette.iter().map(|p| p.convert()).collect::<Vec<Q>>();
+        Pixmap::new_from_fn(self.wide(), self.high(), |xy| {
+            palette[self.indices.get(xy).unwrap().0 as usize]
+        })
+    }
+}
+
 pub fn iter_2d<Xs, Ys> (xs: Xs, ys: Ys)
     -> impl Iterator<Item = (Xs::Item, Ys::Item)>
 where
@@ -215,3 +361,24 @@ where
     ys.flat_map(move |y| xs.clone().map(move |x| (x, y.clone())))
 }
 
+
+#[cfg(test)]
+#[test]
+fn conversions() {
+    for word in 0 ..= u16::MAX {
+        let word = Rgb555(word);
+        assert_eq!(Rgb555::from_rgba(word.to_rgba()), word, "{:04x}", word.0);
+    }
+    assert_eq!(Rgb555::from_rgba(Rgba::BLACK), Rgb555(0x8000));
+    assert_eq!(Luma::from_rgba(Rgba::WHITE), Luma(0xff));
+
+    let indices = Pixmap::new_from_fn(3, 2, |[x, y]| Index((x + y) as u8 % 2));
+    let palette = vec![Rgb555(0x001f), Rgb555::TRANSPARENT];
+    let one = Pixmap::new_from_fn(3, 2, |[x, y]| Index((x + y) as u8 % 2));
+    assert!(Indexed::new(one, vec![Rgb555(0)]).is_none());
+    let indexed = Indexed::new(indices, palette).unwrap();
+    let rgba = indexed.to_pixmap::<Rgba>();
+    assert_eq!(rgba.get([0, 0]), Some(Rgba::new(0xff, 0, 0, 0xff)));
+    assert_eq!(rgba.get([1, 0]), Some(Rgba::TRANSPARENT));
+    assert_eq!(rgba.convert::<Rgb>().get([2, 1]), Some(Rgb([0; 3])));
+}
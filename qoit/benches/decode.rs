@@ -0,0 +1,70 @@
+//! Decoding speed over `test-imgs`: the original decoder, the table-driven one, and the
+//! table-driven one with an image per thread. Run with `cargo bench --bench decode`.
+
+use {
+    qoit::{Header, Pixel, State},
+    std::time::{Duration, Instant},
+};
+
+const ROUNDS: u32 = 50;
+
+fn main() {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/test-imgs");
+    let mut files = std::fs::read_dir(dir).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "qoi"))
+        .map(|path| std::fs::read(path).unwrap())
+        .collect::<Vec<_>>();
+    // biggest first, for decode_parallel
+    files.sort_by_key(|qoif| std::cmp::Reverse(qoif.len()));
+
+    let mut outputs = files.iter()
+        .map(|qoif| {
+            let header = Header::from_bytes(qoif[..14].try_into().unwrap()).unwrap();
+            vec![[0; 4]; header.pixel_count() as usize]
+        })
+        .collect::<Vec<Vec<Pixel>>>();
+    let n_pixels = outputs.iter().map(Vec::len).sum::<usize>();
+    println!("{} images, {:.1} Mpixels", files.len(), n_pixels as f64 / 1e6);
+
+    let mut jobs = files.iter().zip(&mut outputs)
+        .map(|(qoif, output)| (&qoif[14..], &mut output[..]))
+        .collect::<Vec<_>>();
+
+    let reference = |jobs: &mut [(&[u8], &mut [Pixel])]| {
+        for (input, output) in jobs {
+            let progress = State::new().decode_partial_reference(output, input);
+            assert_eq!(progress.pixels, output.len());
+        }
+    };
+    let table = |jobs: &mut [(&[u8], &mut [Pixel])]| {
+        for (input, output) in jobs {
+            State::new().decode_some(output, input).unwrap();
+        }
+    };
+    let parallel = |jobs: &mut [(&[u8], &mut [Pixel])]| {
+        qoit::decode_parallel(jobs).unwrap();
+    };
+
+    // interleaved, so that they all see the same conditions
+    let mut best = [Duration::MAX; 3];
+    for _ in 0 .. ROUNDS {
+        for (f, best) in [&reference as &dyn Fn(&mut _), &table, &parallel].iter().zip(&mut best) {
+            let start = Instant::now();
+            f(&mut jobs);
+            *best = start.elapsed().min(*best);
+        }
+    }
+
+    let threads = std::thread::available_parallelism().map_or(1, usize::from);
+    let [reference, table, parallel] = best;
+    report("reference", reference, reference, n_pixels);
+    report("table", table, reference, n_pixels);
+    report(&format!("parallel ({threads} threads)"), parallel, reference, n_pixels);
+}
+
+fn report(name: &str, time: Duration, reference: Duration, n_pixels: usize) {
+    let mpps = n_pixels as f64 / time.as_secs_f64() / 1e6;
+    let speedup = reference.as_secs_f64() / time.as_secs_f64();
+    println!("{name:>22}: {:>8.2?}  {mpps:>7.1} Mpixel/s  {speedup:>5.2}x", time);
+}
@@ -0,0 +1,151 @@
+//! What `qoitc` and `qoitd` share: arguments, reading and writing files or stdio, and reporting
+//! errors.
+
+// each tool only uses some of this
+#![allow(dead_code)]
+
+use std::{
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+pub type Error = Box<dyn std::error::Error>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Convert,
+    Info,
+    Verify,
+}
+
+pub struct Args {
+    pub mode: Mode,
+    /// A file, or with more than one input, a directory
+    pub output: Option<PathBuf>,
+    pub inputs: Vec<PathBuf>,
+}
+
+impl Args {
+    /// Where to write `input` converted: as given by `-o`, or beside it with `ext` added. stdin
+    /// goes to stdout unless told otherwise.
+    pub fn output_for(&self, input: &Path, ext: &str) -> Result<PathBuf, Error> {
+        let stdin = input == Path::new("-");
+        match &self.output {
+            Some(output) if self.inputs.len() == 1 && !output.is_dir() => Ok(output.clone()),
+            Some(_) if stdin => Err("stdin can't be converted into a directory".into()),
+            Some(dir) => {
+                let mut name = input.file_name().unwrap_or_default().to_owned();
+                name.push(format!(".{ext}"));
+                Ok(dir.join(name))
+            }
+            None if stdin => Ok("-".into()),
+            None => {
+                let mut path = input.as_os_str().to_owned();
+                path.push(format!(".{ext}"));
+                Ok(path.into())
+            }
+        }
+    }
+}
+
+/// Parses the arguments, then runs `convert` on each input, carrying on past failures. `modes`
+/// are the flags besides `-o` the tool takes. Exits with 1 if any input failed, or 2 for bad
+/// arguments.
+pub fn main(
+    usage: &str,
+    modes: &[(&str, Mode)],
+    convert: impl Fn(&Args, &Path) -> Result<(), Error>,
+) -> ExitCode {
+    let name = std::env::args().next().unwrap_or_default();
+    let name = Path::new(&name).file_stem().unwrap_or_default().to_string_lossy().into_owned();
+
+    let args = match parse(std::env::args().skip(1), modes) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{usage}");
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("{name}: {e}\n\n{usage}");
+            return ExitCode::from(2);
+        }
+    };
+
+    let mut failed = false;
+    for input in &args.inputs {
+        if let Err(e) = convert(&args, input) {
+            eprintln!("{name}: {}: {e}", input.display());
+            failed = true;
+        }
+    }
+    if failed {ExitCode::FAILURE} else {ExitCode::SUCCESS}
+}
+
+/// `None` for `--help`
+fn parse(mut args: impl Iterator<Item = String>, modes: &[(&str, Mode)])
+    -> Result<Option<Args>, Error>
+{
+    let mut mode = Mode::Convert;
+    let mut output = None;
+    let mut inputs = Vec::new();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-o" => {
+                let path = args.next().ok_or("-o needs a path")?;
+                if output.replace(PathBuf::from(path)).is_some() {
+                    return Err("more than one -o".into());
+                }
+            }
+            "--" => {
+                inputs.extend(args.by_ref().map(PathBuf::from));
+            }
+            flag if flag.starts_with('-') && flag != "-" => {
+                let &(_, m) = modes.iter()
+                    .find(|(name, _)| *name == flag)
+                    .ok_or_else(|| format!("unknown option {flag}"))?;
+                if mode != Mode::Convert {
+                    return Err("only one mode may be given".into());
+                }
+                mode = m;
+            }
+            _ => inputs.push(PathBuf::from(arg)),
+        }
+    }
+
+    if inputs.is_empty() {
+        return Err("no inputs".into());
+    }
+    if output.is_some() && mode != Mode::Convert {
+        return Err("-o only applies to converting".into());
+    }
+    Ok(Some(Args{mode, output, inputs}))
+}
+
+/// Reads a whole file, or stdin for `-`.
+pub fn read(path: &Path) -> Result<Vec<u8>, Error> {
+    if path == Path::new("-") {
+        let mut bytes = Vec::new();
+        std::io::stdin().lock().read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+    else {
+        Ok(std::fs::read(path)?)
+    }
+}
+
+/// Writes a whole file, or stdout for `-`.
+pub fn write(path: &Path, bytes: &[u8]) -> Result<(), Error> {
+    if path == Path::new("-") {
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(bytes)?;
+        stdout.flush()?;
+    }
+    else {
+        std::fs::write(path, bytes)
+            .map_err(|e| format!("writing {}: {e}", path.display()))?;
+    }
+    Ok(())
+}
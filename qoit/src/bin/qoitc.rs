@@ -1,17 +1,37 @@
-use qoit::encode_qoi_file;
-
-fn main() {
-    let path = std::env::args().nth(1).unwrap();
-    let image = std::fs::read(&path).unwrap();
-    let image = image::load_from_memory(&image).unwrap().into_rgba8();
-    let qoif = encode_qoi_file(
-        qoit::Header {
-            wide: image.width(),
-            high: image.height(),
-            spec: qoit::ColorSpec::Srgb8A8,
-        },
-        bytemuck::cast_slice(&image),
-    ).unwrap();
-    std::fs::write(format!("{path}.qoi"), qoif).unwrap();
+mod cli;
+
+use {
+    cli::{Args, Error, Mode},
+    std::{path::Path, process::ExitCode},
+};
+
+const USAGE: &str = "\
+usage: qoitc [--verify] [-o OUTPUT] INPUT...
+
+Encodes images as QOI, each to INPUT.qoi unless -o gives a file or, with more than one input,
+an existing directory. An INPUT of - is stdin, and goes to stdout unless -o says otherwise; an
+OUTPUT of - is stdout.
+
+  --verify   encode in memory and check that decoding gives back the same pixels";
+
+fn main() -> ExitCode {
+    cli::main(USAGE, &[("--verify", Mode::Verify)], encode)
 }
 
+fn encode(args: &Args, input: &Path) -> Result<(), Error> {
+    let image = image::load_from_memory(&cli::read(input)?)?;
+    let (wide, high) = (image.width(), image.height());
+    let (spec, bytes) =
+        if image.color().has_alpha() {(qoit::ColorSpec::Srgb8A8, image.into_rgba8().into_raw())}
+        else                         {(qoit::ColorSpec::Srgb8,   image.into_rgb8().into_raw())};
+    let qoif = qoit::encode_qoi_bytes(qoit::Header{wide, high, spec}, &bytes)?;
+
+    if args.mode == Mode::Verify {
+        let (_, decoded) = qoit::decode_qoi_bytes(&qoif)?;
+        if decoded != bytes {return Err("pixels changed on the way back".into())}
+        println!("{}: ok, {} bytes", input.display(), qoif.len());
+        return Ok(());
+    }
+
+    cli::write(&args.output_for(input, "qoi")?, &qoif)
+}
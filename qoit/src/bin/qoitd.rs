@@ -1,17 +1,67 @@
-use qoit::decode_qoi_file;
-
-fn main() {
-    let path = std::env::args().nth(1).unwrap();
-    let qoif = std::fs::read(&path).unwrap();
-    let (header, pixels) = decode_qoi_file(&qoif).unwrap();
-
-    image::RgbaImage
-        ::from_vec(
-            header.wide, header.high,
-            bytemuck::cast_vec(pixels),
-        )
-        .unwrap()
-        .save(format!("{path}.png"))
-        .unwrap();
+mod cli;
+
+use {
+    cli::{Args, Error, Mode},
+    image::ImageEncoder as _,
+    std::{path::Path, process::ExitCode},
+};
+
+const USAGE: &str = "\
+usage: qoitd [--info | --verify] [-o OUTPUT] INPUT...
+
+Decodes QOI images, each to INPUT.png unless -o gives a file or, with more than one input, an
+existing directory; a file is written in the format its extension names. An INPUT of - is stdin,
+and goes to stdout as PNG unless -o says otherwise; an OUTPUT of - is stdout.
+
+  --info     print each header and how the size compares to raw pixels
+  --verify   decode, then check that encoding again gives back the same pixels";
+
+fn main() -> ExitCode {
+    cli::main(USAGE, &[("--info", Mode::Info), ("--verify", Mode::Verify)], decode)
 }
 
+fn decode(args: &Args, input: &Path) -> Result<(), Error> {
+    let qoif = cli::read(input)?;
+
+    if args.mode == Mode::Info {
+        let header = qoif.get(.. 14).ok_or(qoit::FileError::InputTooShort)?;
+        let header = qoit::Header::from_bytes(header.try_into().unwrap())?;
+        let channels = header.spec.channels();
+        let space = match header.spec {
+            qoit::ColorSpec::Srgb8 | qoit::ColorSpec::Srgb8A8 => "sRGB",
+            qoit::ColorSpec::Rgb8  | qoit::ColorSpec::Rgba8   => "linear",
+        };
+        let raw = header.pixel_count() * channels as u64;
+        println!(
+            "{}: {}x{}, {channels} channels, {space}, {} bytes, {:.1}% of raw",
+            input.display(), header.wide, header.high, qoif.len(),
+            qoif.len() as f64 / raw as f64 * 100.,
+        );
+        return Ok(());
+    }
+
+    let (header, bytes) = qoit::decode_qoi_bytes(&qoif)?;
+
+    if args.mode == Mode::Verify {
+        let (_, again) = qoit::decode_qoi_bytes(&qoit::encode_qoi_bytes(header, &bytes)?)?;
+        if again != bytes {return Err("pixels changed on the way back".into())}
+        println!("{}: ok", input.display());
+        return Ok(());
+    }
+
+    let color = match header.spec.channels() {
+        3 => image::ColorType::Rgb8,
+        _ => image::ColorType::Rgba8,
+    };
+    let output = args.output_for(input, "png")?;
+    if output == Path::new("-") {
+        let mut png = Vec::new();
+        image::codecs::png::PngEncoder::new(&mut png)
+            .write_image(&bytes, header.wide, header.high, color)?;
+        cli::write(&output, &png)
+    }
+    else {
+        image::save_buffer(&output, &bytes, header.wide, header.high, color)
+            .map_err(|e| format!("writing {}: {e}", output.display()).into())
+    }
+}
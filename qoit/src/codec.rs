@@ -0,0 +1,174 @@
+//! Codecs for the `image` crate, for `DynamicImage::from_decoder` and the like. `image` has no
+//! way to add formats to its own `open` and `save_buffer`, so [`open`] and [`save_buffer`] stand
+//! in for those.
+
+use {
+    crate::{Header, ColorSpec, FileError, Pixel, QoiReader, QoiWriter},
+    ::image::{
+        ColorType, DynamicImage, ImageDecoder, ImageEncoder, ImageError, ImageFormat,
+        ImageResult,
+        error::{DecodingError, EncodingError, ImageFormatHint, UnsupportedError,
+                UnsupportedErrorKind},
+    },
+    std::{fs::File, io::{Cursor, Read, Write}, path::Path},
+};
+
+fn hint() -> ImageFormatHint {
+    ImageFormatHint::Exact(ImageFormat::Qoi)
+}
+
+fn decoding_error(e: FileError) -> ImageError {
+    match e {
+        FileError::Io(e) => ImageError::IoError(e),
+        e => ImageError::Decoding(DecodingError::new(hint(), e)),
+    }
+}
+
+fn encoding_error(e: FileError) -> ImageError {
+    match e {
+        FileError::Io(e) => ImageError::IoError(e),
+        e => ImageError::Encoding(EncodingError::new(hint(), e)),
+    }
+}
+
+pub struct QoiDecoder<R> {
+    reader: QoiReader<R>,
+    header: Header,
+}
+
+impl<R: Read> QoiDecoder<R> {
+    /// Reads the header, ready to decode.
+    pub fn new(inner: R) -> ImageResult<Self> {
+        let reader = QoiReader::new(inner).map_err(decoding_error)?;
+        let header = reader.header().unwrap();
+        Ok(QoiDecoder{reader, header})
+    }
+
+    pub fn header(&self) -> Header {
+        self.header
+    }
+}
+
+impl<'a, R: Read + 'a> ImageDecoder<'a> for QoiDecoder<R> {
+    type Reader = Cursor<Vec<u8>>;
+
+    fn dimensions(&self) -> (u32, u32) {
+        (self.header.wide, self.header.high)
+    }
+
+    /// 8-bit RGB or RGBA, as the header says
+    fn color_type(&self) -> ColorType {
+        match self.header.spec.channels() {
+            3 => ColorType::Rgb8,
+            _ => ColorType::Rgba8,
+        }
+    }
+
+    fn into_reader(self) -> ImageResult<Self::Reader> {
+        let mut bytes = vec![0; self.total_bytes() as usize];
+        self.read_image(&mut bytes)?;
+        Ok(Cursor::new(bytes))
+    }
+
+    /// Decodes a row at a time straight into `buf`.
+    fn read_image(mut self, buf: &mut [u8]) -> ImageResult<()> {
+        assert_eq!(buf.len() as u64, self.total_bytes());
+        let channels = self.header.spec.channels();
+        let mut row = vec![[0; 4]; self.header.wide as usize];
+        for out in buf.chunks_exact_mut(row.len() * channels) {
+            self.reader.read_pixels(&mut row).map_err(decoding_error)?;
+            for (out, pixel) in out.chunks_exact_mut(channels).zip(&row) {
+                out.copy_from_slice(&pixel[..channels]);
+            }
+        }
+        self.reader.finish().map_err(decoding_error)?;
+        Ok(())
+    }
+}
+
+pub struct QoiEncoder<W> {
+    inner: W,
+}
+
+impl<W: Write> QoiEncoder<W> {
+    pub fn new(inner: W) -> Self {
+        QoiEncoder{inner}
+    }
+}
+
+impl<W: Write> ImageEncoder for QoiEncoder<W> {
+    /// Takes 8-bit luma or RGB, with or without alpha. Luma is written as grey RGB.
+    fn write_image(self, buf: &[u8], wide: u32, high: u32, color: ColorType)
+        -> ImageResult<()>
+    {
+        let to_pixel: fn(&[u8]) -> Pixel = match color {
+            ColorType::L8    => |p: &[u8]| [p[0], p[0], p[0], 0xff],
+            ColorType::La8   => |p: &[u8]| [p[0], p[0], p[0], p[1]],
+            ColorType::Rgb8  => |p: &[u8]| [p[0], p[1], p[2], 0xff],
+            ColorType::Rgba8 => |p: &[u8]| [p[0], p[1], p[2], p[3]],
+            _ => {
+                let kind = UnsupportedErrorKind::Color(color.into());
+                let e = UnsupportedError::from_format_and_kind(hint(), kind);
+                return Err(ImageError::Unsupported(e));
+            }
+        };
+        let spec = if color.has_alpha() {ColorSpec::Srgb8A8} else {ColorSpec::Srgb8};
+        let header = Header{wide, high, spec};
+
+        let channels = color.bytes_per_pixel() as usize;
+        let expected = header.pixel_count() * channels as u64;
+        if buf.len() as u64 != expected {
+            let e = FileError::WrongLength{expected, got: buf.len() as u64};
+            return Err(encoding_error(e));
+        }
+
+        let mut writer = QoiWriter::new(self.inner, header).map_err(encoding_error)?;
+        let mut row = Vec::<Pixel>::with_capacity(wide as usize);
+        for bytes in buf.chunks_exact(wide as usize * channels) {
+            row.clear();
+            row.extend(bytes.chunks_exact(channels).map(to_pixel));
+            writer.write_pixels(&row).map_err(encoding_error)?;
+        }
+        writer.finish().map_err(encoding_error)?;
+        Ok(())
+    }
+}
+
+/// Like `image::open`, but only for QOI.
+pub fn open(path: impl AsRef<Path>) -> ImageResult<DynamicImage> {
+    let file = File::open(path).map_err(ImageError::IoError)?;
+    DynamicImage::from_decoder(QoiDecoder::new(file)?)
+}
+
+/// Like `image::save_buffer`, but always QOI, whatever the extension.
+pub fn save_buffer(path: impl AsRef<Path>, buf: &[u8], wide: u32, high: u32, color: ColorType)
+    -> ImageResult<()>
+{
+    let file = File::create(path).map_err(ImageError::IoError)?;
+    QoiEncoder::new(file).write_image(buf, wide, high, color)
+}
+
+#[cfg(test)]
+#[test]
+fn round_trip() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/test-imgs/dice.qoi");
+    let qoif = std::fs::read(path).unwrap();
+    let (_, pixels) = crate::decode_qoi_file(&qoif).unwrap();
+
+    let image = DynamicImage::from_decoder(QoiDecoder::new(&qoif[..]).unwrap()).unwrap();
+    assert_eq!(image.as_bytes(), pixels.concat());
+
+    let mut bytes = Vec::new();
+    let (wide, high) = (image.width(), image.height());
+    QoiEncoder::new(&mut bytes).write_image(image.as_bytes(), wide, high, image.color()).unwrap();
+    assert_eq!(bytes, qoif);
+
+    let grey = image.to_luma8();
+    let mut bytes = Vec::new();
+    QoiEncoder::new(&mut bytes).write_image(&grey, wide, high, ColorType::L8).unwrap();
+    let again = DynamicImage::from_decoder(QoiDecoder::new(&bytes[..]).unwrap()).unwrap();
+    assert_eq!(again.to_luma8(), grey);
+
+    let short = QoiEncoder::new(Vec::new()).write_image(&grey.as_raw()[1..], wide, high, ColorType::L8);
+    assert!(matches!(short, Err(ImageError::Encoding(_))));
+}
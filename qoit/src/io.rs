@@ -0,0 +1,182 @@
+//! Streaming over `std::io`, for images or image streams too big to want in memory at once.
+//! Both sides work through a fixed-size buffer.
+
+use {
+    crate::{State, Pixel, Header, FileError, DecodeError, PADDING},
+    std::io::{Read, Write},
+};
+
+const BUFFER_SIZE: usize = 0x1000;
+
+/// Longest op, in bytes
+const MAX_OP: usize = 5;
+
+pub struct QoiReader<R> {
+    inner: R,
+    header: Option<Header>,
+    state: State,
+    buf: Box<[u8; BUFFER_SIZE]>,
+    start: usize,
+    end: usize,
+}
+
+impl<R: Read> QoiReader<R> {
+    /// Reads a `.qoi` file's header, ready to read its pixels.
+    pub fn new(inner: R) -> Result<Self, FileError> {
+        let mut reader = Self::headerless(inner);
+        let mut header = [0; 14];
+        reader.read_bytes(&mut header)?;
+        reader.header = Some(Header::from_bytes(header).map_err(FileError::Header)?);
+        Ok(reader)
+    }
+
+    /// Reads bare ops with no header or padding, like an image set's `qoi_stream`.
+    pub fn headerless(inner: R) -> Self {
+        QoiReader {
+            inner,
+            header: None,
+            state: State::new(),
+            buf: Box::new([0; BUFFER_SIZE]),
+            start: 0,
+            end: 0,
+        }
+    }
+
+    pub fn header(&self) -> Option<Header> {
+        self.header
+    }
+
+    /// Fills `output` with the next pixels; a row's worth at a time keeps memory use down.
+    pub fn read_pixels(&mut self, mut output: &mut [Pixel]) -> Result<(), FileError> {
+        loop {
+            let progress = self.state.decode_partial(output, &self.buf[self.start .. self.end]);
+            self.start += progress.bytes;
+            output = &mut output[progress.pixels..];
+            if output.is_empty() {return Ok(())}
+            if self.fill()? == 0 {return Err(FileError::Decode(DecodeError::Underrun))}
+        }
+    }
+
+    /// Checks the end-of-stream padding and that nothing follows it, if there's a header, and
+    /// gives back the inner reader. Without a header, anything buffered past the end is lost.
+    pub fn finish(mut self) -> Result<R, FileError> {
+        if self.header.is_some() {
+            let mut padding = [0; 8];
+            self.read_bytes(&mut padding)?;
+            if padding != PADDING {return Err(FileError::BadPadding)}
+            let mut trailing = (self.end - self.start) as u64;
+            loop {
+                self.start = self.end;
+                match self.fill()? {
+                    0 => break,
+                    n => trailing += n as u64,
+                }
+            }
+            if trailing != 0 {return Err(FileError::TrailingData(trailing))}
+        }
+        Ok(self.inner)
+    }
+
+    fn read_bytes(&mut self, out: &mut [u8]) -> Result<(), FileError> {
+        while self.end - self.start < out.len() {
+            if self.fill()? == 0 {return Err(FileError::InputTooShort)}
+        }
+        out.copy_from_slice(&self.buf[self.start .. self.start + out.len()]);
+        self.start += out.len();
+        Ok(())
+    }
+
+    /// Moves any unread bytes (at most a partial op) to the front and reads more after them.
+    fn fill(&mut self) -> Result<usize, FileError> {
+        debug_assert!(self.end - self.start < MAX_OP.max(14));
+        self.buf.copy_within(self.start .. self.end, 0);
+        self.end -= self.start;
+        self.start = 0;
+        let n = loop {
+            match self.inner.read(&mut self.buf[self.end..]) {
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                result => break result.map_err(FileError::Io)?,
+            }
+        };
+        self.end += n;
+        Ok(n)
+    }
+}
+
+pub struct QoiWriter<W: Write> {
+    inner: W,
+    header: Option<Header>,
+    written: u64,
+    state: State,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> QoiWriter<W> {
+    /// Writes `header`, ready for the pixels.
+    pub fn new(mut inner: W, header: Header) -> Result<Self, FileError> {
+        header.validate().map_err(FileError::Header)?;
+        inner.write_all(&header.to_bytes()).map_err(FileError::Io)?;
+        Ok(QoiWriter{header: Some(header), ..Self::headerless(inner)})
+    }
+
+    /// Writes bare ops, with no header or padding.
+    pub fn headerless(inner: W) -> Self {
+        QoiWriter {
+            inner,
+            header: None,
+            written: 0,
+            state: State::new(),
+            buf: Vec::with_capacity(BUFFER_SIZE),
+        }
+    }
+
+    pub fn write_pixels(&mut self, pixels: &[Pixel]) -> Result<(), FileError> {
+        for chunk in pixels.chunks(BUFFER_SIZE / MAX_OP) {
+            self.state.encode_some(&mut self.buf, chunk).map_err(FileError::Encode)?;
+            self.inner.write_all(&self.buf).map_err(FileError::Io)?;
+            self.buf.clear();
+        }
+        self.written += pixels.len() as u64;
+        Ok(())
+    }
+
+    /// Ends any pending run, writes the padding if there's a header, and gives back the inner
+    /// writer. With a header, the right number of pixels must have been written.
+    pub fn finish(mut self) -> Result<W, FileError> {
+        self.state.encode_flush(&mut self.buf).map_err(FileError::Encode)?;
+        if let Some(header) = self.header {
+            let expected = header.pixel_count();
+            if self.written != expected {
+                return Err(FileError::WrongLength{expected, got: self.written});
+            }
+            self.buf.extend_from_slice(&PADDING);
+        }
+        self.inner.write_all(&self.buf).map_err(FileError::Io)?;
+        self.inner.flush().map_err(FileError::Io)?;
+        Ok(self.inner)
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn round_trip() {
+    let pixels = (0 .. 3000u32)
+        .map(|i| if i % 7 < 3 {[0, 0, 0, 255]} else {i.wrapping_mul(2654435761).to_le_bytes()})
+        .collect::<Vec<Pixel>>();
+    let header = Header{wide: 100, high: 30, spec: crate::ColorSpec::Srgb8A8};
+
+    let mut writer = QoiWriter::new(Vec::new(), header).unwrap();
+    for row in pixels.chunks(100) {
+        writer.write_pixels(row).unwrap();
+    }
+    let bytes = writer.finish().unwrap();
+    assert_eq!(bytes, crate::encode_qoi_file(header, &pixels).unwrap());
+
+    let mut reader = QoiReader::new(&bytes[..]).unwrap();
+    let mut row = [[0; 4]; 100];
+    for expected in pixels.chunks(100) {
+        reader.read_pixels(&mut row).unwrap();
+        assert_eq!(row[..], *expected);
+    }
+    reader.finish().unwrap();
+}
@@ -1,18 +1,65 @@
-#![feature(slice_take)]
-#![feature(extend_one)]
+#![cfg_attr(not(feature = "std"), no_std)]
 
-#[derive(Debug, thiserror::Error)]
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+use core::fmt;
+
+pub mod psx;
+
+#[cfg(feature = "std")]
+mod io;
+#[cfg(feature = "std")]
+mod par;
+#[cfg(feature = "std")]
+pub use {
+    io::{QoiReader, QoiWriter},
+    par::decode_parallel,
+};
+
+#[cfg(feature = "image")]
+pub mod codec;
+#[cfg(feature = "image")]
+pub use codec::{QoiDecoder, QoiEncoder};
+
+#[derive(Debug)]
 pub enum DecodeError {
-    #[error("Input underrun")]
     Underrun,
 }
 
-#[derive(Debug, thiserror::Error)]
+#[derive(Debug)]
 pub enum EncodeError {
-    #[error("Output buffer overrun")]
     Overrun,
+    /// Only from [`psx`] encoding
+    NotPsx,
 }
 
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Underrun => f.write_str("Input underrun"),
+        }
+    }
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncodeError::Overrun => f.write_str("Output buffer overrun"),
+            EncodeError::NotPsx => f.write_str("Pixel has no PSX colour word"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DecodeError { }
+
+#[cfg(feature = "std")]
+impl std::error::Error for EncodeError { }
+
 pub type Pixel = [u8; 4];
 
 fn hash_pixel([r, g, b, a]: Pixel) -> u8 { 
@@ -39,6 +86,76 @@ impl State {
     }
 }
 
+#[derive(Debug, Clone, Copy)]
+struct OpInfo {
+    len: u8,
+    /// For diffs, the change to the previous pixel; for lumas, its green part. As a
+    /// little-endian word, to add to one.
+    delta: u32,
+}
+
+/// Each op's length and, where it's fixed by the first byte, change to the previous pixel
+static OPS: [OpInfo; 256] = {
+    let mut ops = [OpInfo{len: 1, delta: 0}; 256];
+    let mut b0 = 0;
+    while b0 < 256 {
+        let b = b0 as u8;
+        ops[b0] = match b {
+            0x00 ..= 0x3f => OpInfo{len: 1, delta: 0},
+            0x40 ..= 0x7f => {
+                let [dr, dg, db] = [b >> 4 & 0x3, b >> 2 & 0x3, b & 0x3];
+                let delta = [dr.wrapping_sub(2), dg.wrapping_sub(2), db.wrapping_sub(2), 0];
+                OpInfo{len: 1, delta: u32::from_le_bytes(delta)}
+            }
+            0x80 ..= 0xbf => {
+                let dg = (b & 0x3f).wrapping_sub(32);
+                OpInfo{len: 2, delta: u32::from_le_bytes([dg, dg, dg, 0])}
+            }
+            0xfe => OpInfo{len: 4, delta: 0},
+            0xff => OpInfo{len: 5, delta: 0},
+            _    => OpInfo{len: 1, delta: 0},
+        };
+        b0 += 1;
+    }
+    ops
+};
+
+/// A luma op's red and blue changes relative to green, by its second byte
+static LUMA_RB: [u32; 256] = {
+    let mut deltas = [0; 256];
+    let mut b1 = 0;
+    while b1 < 256 {
+        let b = b1 as u8;
+        let delta = [(b >> 4).wrapping_sub(8), 0, (b & 0xf).wrapping_sub(8), 0];
+        deltas[b1] = u32::from_le_bytes(delta);
+        b1 += 1;
+    }
+    deltas
+};
+
+/// `hash_pixel` for a pixel as a little-endian word. Spreading the channels out to bytes 0, 2,
+/// 5 and 7 lets one multiply sum them, each times its factor, into the top byte with no
+/// carries from below.
+#[inline(always)]
+fn hash_word(pixel: u32) -> usize {
+    let v = pixel as u64;
+    let spread = (v << 32 | v) & 0xff00_ff00_00ff_00ff;
+    (spread.wrapping_mul(0x0300_0700_0005_000b) >> 56) as usize & 0x3f
+}
+
+/// Adds each byte of `b` to that of `a`, wrapping
+#[inline(always)]
+fn add(a: u32, b: u32) -> u32 {
+    ((a & 0x7f7f_7f7f) + (b & 0x7f7f_7f7f)) ^ ((a ^ b) & 0x8080_8080)
+}
+
+/// How far a call to [`State::decode_partial`] got.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    pub pixels: usize,
+    pub bytes: usize,
+}
+
 impl State {
     pub fn decode_some<'s, 'p, 'b>(&'s mut self, output: &'p mut [Pixel], bytes: &'b [u8])
         -> Result<&'b [u8], DecodeError>
@@ -46,6 +163,93 @@ impl State {
         'p: 's,
         'b: 's,
     {
+        let progress = self.decode_partial(output, bytes);
+        if progress.pixels < output.len() {return Err(DecodeError::Underrun)}
+        Ok(&bytes[progress.bytes..])
+    }
+
+    /// Decodes into `output` until it's full or `bytes` runs out, which may be partway through
+    /// an op; the unconsumed tail can be passed again, with more input after it, to carry on.
+    pub fn decode_partial(&mut self, output: &mut [Pixel], bytes: &[u8]) -> Progress {
+        let mut i = 0;
+        let mut o = 0;
+        // Pixels are handled as little-endian words here, so they stay whole in registers.
+        let mut prev = u32::from_le_bytes(self.prev);
+
+        // a run left over from last time
+        if self.run != 0 {
+            let n = self.run.min(output.len());
+            output[.. n].fill(prev.to_le_bytes());
+            o = n;
+            self.run -= n;
+        }
+
+        // Ops are read through a window as long as the longest, so this only goes as far as
+        // that fits; the reference decoder takes care of the last few bytes. The branch is on
+        // the byte itself rather than something from the table, which measured slower.
+        while o < output.len() {
+            let Some(op) = bytes.get(i .. i + 5) else {break};
+            let op: [u8; 5] = op.try_into().unwrap();
+            let info = OPS[op[0] as usize];
+
+            let pixel = match op[0] {
+                0x00 ..= 0x3f => u32::from_le_bytes(self.array[op[0] as usize & 0x3f]),
+                0x40 ..= 0x7f => add(prev, info.delta),
+                0x80 ..= 0xbf => add(prev, add(info.delta, LUMA_RB[op[1] as usize])),
+
+                0xc0 ..= 0xfd => {
+                    // as the encoder does, since the starting pixel may not be indexed yet
+                    self.array[hash_word(prev)] = prev.to_le_bytes();
+                    let run = (op[0] & 0x3f) as usize + 1;
+                    let n = run.min(output.len() - o);
+                    output[o .. o + n].fill(prev.to_le_bytes());
+                    o += n;
+                    self.run = run - n;
+                    i += 1;
+                    continue;
+                }
+
+                // Literals come in long strings in noisy images, so take them all here rather
+                // than going back round the outer loop for each.
+                0xfe | 0xff => {
+                    let mut op = op;
+                    loop {
+                        let rgb = u32::from_le_bytes([op[1], op[2], op[3], 0]);
+                        prev = match op[0] {
+                            0xff => rgb | (op[4] as u32) << 24,
+                            _    => rgb | prev & 0xff00_0000,
+                        };
+                        i += 4 + (op[0] & 1) as usize;
+                        output[o] = prev.to_le_bytes();
+                        o += 1;
+                        self.array[hash_word(prev)] = prev.to_le_bytes();
+
+                        if o == output.len() {break}
+                        match bytes.get(i .. i + 5) {
+                            Some(next) if next[0] >= 0xfe => op = next.try_into().unwrap(),
+                            _ => break,
+                        }
+                    }
+                    continue;
+                }
+            };
+
+            i += info.len as usize;
+            prev = pixel;
+            output[o] = pixel.to_le_bytes();
+            o += 1;
+            self.array[hash_word(pixel)] = pixel.to_le_bytes();
+        }
+
+        self.prev = prev.to_le_bytes();
+        let tail = self.decode_partial_reference(&mut output[o..], &bytes[i..]);
+        Progress{pixels: o + tail.pixels, bytes: i + tail.bytes}
+    }
+
+    /// The original slice-pattern decoder, kept to check and benchmark `decode_partial` against.
+    #[doc(hidden)]
+    pub fn decode_partial_reference(&mut self, output: &mut [Pixel], bytes: &[u8]) -> Progress {
+        let n_out = output.len();
         let cursor_in = &mut &bytes[..];
         let cursor_out = &mut &mut output[..];
 
@@ -90,11 +294,13 @@ impl State {
                 &[b0@0xc0..=0xfd, ref rest@..] => {
                     debug_assert_eq!(self.run, 0);
                     self.run = (b0 & 0x3f) as usize + 1;
+                    // as the encoder does, since the starting pixel may not be indexed yet
+                    self.array[hash_pixel(self.prev) as usize] = self.prev;
                     *cursor_in = rest;
                     continue;
                 }
 
-                _ => return Err(DecodeError::Underrun),
+                _ => break,
             };
 
             let pixel_out;
@@ -105,102 +311,139 @@ impl State {
             self.array[hash as usize] = pixel;
         }
 
-        Ok(*cursor_in)
+        Progress {
+            pixels: n_out - cursor_out.len(),
+            bytes:  bytes.len() - cursor_in.len(),
+        }
     }
 
-    pub fn encode_flush<'s, 'b, Output>(&'s mut self, output: &mut Output)
-        -> Result<(), EncodeError>
-    where
-        'b: 's,
-        Output: Extend<u8> + 'b,
-    {
+    /// Ends any pending run. On overrun the run is kept, so this can be tried again.
+    pub fn encode_flush(&mut self, output: &mut impl Put) -> Result<(), EncodeError> {
         if self.run != 0 {
             debug_assert!(self.run <= 62);
-            output.extend_one(0xc0 | self.run as u8 - 1);
+            output.put(&[0xc0 | (self.run as u8 - 1)])?;
         }
         self.run = 0;
 
         Ok(())
     }
 
-    pub fn encode_some<'s, 'b, 'p, Output>(&'s mut self, output: &mut Output, pixels: &'p [Pixel])
+    pub fn encode_some(&mut self, output: &mut impl Put, pixels: &[Pixel])
         -> Result<(), EncodeError>
-    where
-        'b: 's,
-        'p: 's,
-        Output: Extend<u8> + 'b,
     {
         for &pixel in pixels {
-            let hash = hash_pixel(pixel);
+            self.encode_pixel(output, pixel)?;
+        }
+        Ok(())
+    }
+
+    /// Encodes into `output` until it's full or `pixels` runs out. Each pixel's ops are written
+    /// whole or not at all, so the rest of `pixels` can be passed again, with more room, to
+    /// carry on.
+    pub fn encode_partial(&mut self, output: &mut [u8], pixels: &[Pixel]) -> Progress {
+        let n_out = output.len();
+        let mut cursor = &mut output[..];
+        let pixels_done = pixels.iter()
+            .take_while(|&&pixel| self.encode_pixel(&mut cursor, pixel).is_ok())
+            .count();
+        Progress {
+            pixels: pixels_done,
+            bytes:  n_out - cursor.len(),
+        }
+    }
+
+    /// Encodes one pixel, leaving the state as it was if `output` can't take the result.
+    fn encode_pixel(&mut self, output: &mut impl Put, pixel: Pixel) -> Result<(), EncodeError> {
+        let hash = hash_pixel(pixel);
 
-            if pixel == self.prev {
+        if pixel == self.prev {
+            if self.run == 61 {
+                output.put(&[0xc0 | 61])?;
+                self.run = 0;
+            }
+            else {
                 self.run += 1;
-                if self.run == 62 {
-                    self.encode_flush(output)?;
-                }
+            }
+        }
+        else {
+            // room for the end of a run, then the longest op
+            let mut ops = [0; 6];
+            let mut n = 0;
+            let mut op = |bytes: &[u8]| {
+                ops[n .. n + bytes.len()].copy_from_slice(bytes);
+                n += bytes.len();
+            };
+
+            if self.run != 0 {
+                op(&[0xc0 | (self.run as u8 - 1)]);
+            }
+
+            let index_pixel = self.array[hash as usize];
+
+            if pixel == index_pixel {
+                op(&[hash]);
+            }
+            else if pixel[3] != self.prev[3] {
+                op(&[0xff, pixel[0], pixel[1], pixel[2], pixel[3]]);
             }
             else {
-                self.encode_flush(output)?;
+                let dr = pixel[0].wrapping_sub(self.prev[0]);
+                let dg = pixel[1].wrapping_sub(self.prev[1]);
+                let db = pixel[2].wrapping_sub(self.prev[2]);
+
+                let dr2 = dr.wrapping_add(2);
+                let dg2 = dg.wrapping_add(2);
+                let db2 = db.wrapping_add(2);
 
-                let index_pixel = self.array[hash as usize];
+                let dg32 = dg.wrapping_add(32);
+                let drdg8 = dr.wrapping_sub(dg).wrapping_add(8);
+                let dbdg8 = db.wrapping_sub(dg).wrapping_add(8);
 
-                if pixel == index_pixel {
-                    output.extend_one(hash);
+                if dr2 < 4 && dg2 < 4 && db2 < 4 {
+                    op(&[0x40 | dr2 << 4 | dg2 << 2 | db2]);
                 }
-                else if pixel[3] != self.prev[3] {
-                    output.extend([0xff, pixel[0], pixel[1], pixel[2], pixel[3]]);
+                else if dg32 < 64 && drdg8 < 16 && dbdg8 < 16 {
+                    op(&[0x80 | dg32, drdg8 << 4 | dbdg8]);
                 }
                 else {
-                    let dr = pixel[0].wrapping_sub(self.prev[0]);
-                    let dg = pixel[1].wrapping_sub(self.prev[1]);
-                    let db = pixel[2].wrapping_sub(self.prev[2]);
-
-                    let dr2 = dr.wrapping_add(2);
-                    let dg2 = dg.wrapping_add(2);
-                    let db2 = db.wrapping_add(2);
-
-                    let dg32 = dg.wrapping_add(32);
-                    let drdg8 = dr.wrapping_sub(dg).wrapping_add(8);
-                    let dbdg8 = db.wrapping_sub(dg).wrapping_add(8);
-
-                    if dr2 < 4 && dg2 < 4 && db2 < 4 {
-                        output.extend([0x40 | dr2 << 4 | dg2 << 2 | db2]);
-                    }
-                    else if dg32 < 64 && drdg8 < 16 && dbdg8 < 16 {
-                        output.extend([0x80 | dg32, drdg8 << 4 | dbdg8]);
-                    }
-                    else {
-                        output.extend([0xfe, pixel[0], pixel[1], pixel[2]]);
-                    }
+                    op(&[0xfe, pixel[0], pixel[1], pixel[2]]);
                 }
-
-                self.prev = pixel;
             }
 
-            self.array[hash as usize] = pixel;
+            output.put(&ops[..n])?;
+            self.run = 0;
+            self.prev = pixel;
         }
 
+        self.array[hash as usize] = pixel;
         Ok(())
     }
 }
 
-/*trait Put {
-    fn put(&mut self, byte: u8) -> Result<(), EncodeError>;
-    fn put_many<const N: usize>(&mut self, bytes: [u8; N]) -> Result<(), EncodeError>;
+/// Somewhere to put encoded bytes.
+pub trait Put {
+    /// Puts all of `bytes`, or none of them if there isn't room.
+    fn put(&mut self, bytes: &[u8]) -> Result<(), EncodeError>;
 }
 
+/// Fills the slice from the front, leaving it as the part not yet filled.
 impl Put for &mut [u8] {
-    fn put(&mut self, byte: u8) -> Result<(), EncodeError> {
-        *self.take_first_mut().ok_or(EncodeError::Overrun)? = byte;
+    fn put(&mut self, bytes: &[u8]) -> Result<(), EncodeError> {
+        if self.len() < bytes.len() {return Err(EncodeError::Overrun)}
+        let (out, rest) = core::mem::take(self).split_at_mut(bytes.len());
+        out.copy_from_slice(bytes);
+        *self = rest;
         Ok(())
     }
+}
 
-    fn put_many<const N: usize>(&mut self, bytes: [u8; N]) -> Result<(), EncodeError> {
-        let out = self.take_mut(..bytes.len()).ok_or(EncodeError::Overrun)?;
-        out.copy_from_slice(&bytes);
+#[cfg(feature = "alloc")]
+impl Put for Vec<u8> {
+    fn put(&mut self, bytes: &[u8]) -> Result<(), EncodeError> {
+        self.extend_from_slice(bytes);
         Ok(())
     }
-}*/
+}
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u16)]
@@ -211,6 +454,13 @@ pub enum ColorSpec {
     Rgba8   = 0x0104,
 }
 
+impl ColorSpec {
+    /// 3 or 4; with 3, every pixel is opaque.
+    pub fn channels(self) -> usize {
+        (self as u16 & 0xff) as usize
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Header {
     pub wide: u32,
@@ -218,17 +468,43 @@ pub struct Header {
     pub spec: ColorSpec,
 }
 
-#[derive(Debug, thiserror::Error)]
+/// Most pixels an image may have, as in the reference implementation
+pub const MAX_PIXELS: u64 = 400_000_000;
+
+#[derive(Debug)]
 pub enum HeaderError {
-    #[error("file header has wrong magic (not 'qoif')")]
     WrongMagic,
-    #[error("either image dimension is zero")]
     ZeroDimension,
-    #[error("invalid channel count or color space")]
+    TooLarge,
     BadColorSpec,
 }
 
+impl fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            HeaderError::WrongMagic    => "file header has wrong magic (not 'qoif')",
+            HeaderError::ZeroDimension => "either image dimension is zero",
+            HeaderError::TooLarge      => "image has more than 400 million pixels",
+            HeaderError::BadColorSpec  => "invalid channel count or color space",
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for HeaderError { }
+
 impl Header {
+    pub fn pixel_count(&self) -> u64 {
+        self.wide as u64 * self.high as u64
+    }
+
+    /// Checks the dimensions are in range.
+    pub fn validate(&self) -> Result<(), HeaderError> {
+        if self.wide == 0 || self.high == 0 {return Err(HeaderError::ZeroDimension)}
+        if self.pixel_count() >= MAX_PIXELS {return Err(HeaderError::TooLarge)}
+        Ok(())
+    }
+
     pub fn to_bytes(&self) -> [u8; 14] {
         let mut buf = [0u8; 14];
         buf[ 0.. 4].copy_from_slice(b"qoif");
@@ -242,7 +518,6 @@ impl Header {
         if &bytes[0..4] != b"qoif" {return Err(HeaderError::WrongMagic)}
         let wide = u32::from_be_bytes(bytes[4.. 8].try_into().unwrap());
         let high = u32::from_be_bytes(bytes[8..12].try_into().unwrap());
-        if wide == 0 || high == 0 {return Err(HeaderError::ZeroDimension)}
         let spec = match u16::from_le_bytes(bytes[12..14].try_into().unwrap()) {
             0x0003 => ColorSpec::Srgb8,
             0x0004 => ColorSpec::Srgb8A8,
@@ -250,7 +525,9 @@ impl Header {
             0x0104 => ColorSpec::Rgba8,
             _ => return Err(HeaderError::BadColorSpec)
         };
-        Ok(Header{wide, high, spec})
+        let header = Header{wide, high, spec};
+        header.validate()?;
+        Ok(header)
     }
 }
 
@@ -259,10 +536,50 @@ pub enum FileError {
     Decode(DecodeError),
     Encode(EncodeError),
     Header(HeaderError),
+    #[cfg(feature = "std")]
+    Io(std::io::Error),
     InputTooShort,
     BadPadding,
+    /// Input to encode doesn't match the header; in pixels, or bytes for `encode_qoi_bytes`
+    WrongLength{expected: u64, got: u64},
+    /// Bytes after the end padding
+    TrailingData(u64),
 }
 
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileError::Decode(e) => write!(f, "decoding: {e}"),
+            FileError::Encode(e) => write!(f, "encoding: {e}"),
+            FileError::Header(e) => write!(f, "bad header: {e}"),
+            #[cfg(feature = "std")]
+            FileError::Io(e)     => write!(f, "i/o: {e}"),
+            FileError::InputTooShort => f.write_str("input too short to be a QOI file"),
+            FileError::BadPadding    => f.write_str("missing or malformed end padding"),
+            FileError::WrongLength{expected, got} =>
+                write!(f, "input length is {got}, but the header calls for {expected}"),
+            FileError::TrailingData(n) => write!(f, "{n} bytes of trailing data after the end"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for FileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FileError::Decode(e) => Some(e),
+            FileError::Encode(e) => Some(e),
+            FileError::Header(e) => Some(e),
+            FileError::Io(e)     => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Ends every file
+pub const PADDING: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 1];
+
+#[cfg(feature = "alloc")]
 pub fn decode_qoi_file(bytes: &[u8]) -> Result<(Header, Vec<Pixel>), FileError> {
     if bytes.len() < (14 + 1 + 8) {return Err(FileError::InputTooShort)}
     let header = Header::from_bytes(bytes[0..14].try_into().unwrap())
@@ -272,21 +589,58 @@ pub fn decode_qoi_file(bytes: &[u8]) -> Result<(Header, Vec<Pixel>), FileError>
     let mut state = State::new();
     let rest = state.decode_some(&mut pixels, &bytes[14..])
         .map_err(FileError::Decode)?;
-    if rest != [0, 0, 0, 0, 0, 0, 0, 1] {return Err(FileError::BadPadding)}
+    let Some(trailing) = rest.strip_prefix(&PADDING) else {return Err(FileError::BadPadding)};
+    if !trailing.is_empty() {return Err(FileError::TrailingData(trailing.len() as u64))}
     Ok((header, pixels))
 }
 
-// TODO validate header
+/// Like [`decode_qoi_file`], but giving `header.spec.channels()` bytes per pixel.
+#[cfg(feature = "alloc")]
+pub fn decode_qoi_bytes(bytes: &[u8]) -> Result<(Header, Vec<u8>), FileError> {
+    let (header, pixels) = decode_qoi_file(bytes)?;
+    let channels = header.spec.channels();
+    let bytes = pixels.iter().flat_map(|pixel| &pixel[..channels]).copied().collect();
+    Ok((header, bytes))
+}
+
+#[cfg(feature = "alloc")]
 pub fn encode_qoi_file(header: Header, pixels: &[Pixel]) -> Result<Vec<u8>, FileError> {
+    header.validate().map_err(FileError::Header)?;
+    check_length(header.pixel_count(), pixels.len())?;
     let mut bytes = Vec::new();
     bytes.extend_from_slice(&header.to_bytes());
     let mut state = State::new();
-    state.encode_some(&mut bytes, pixels).map_err(FileError::Encode)?;
+    match header.spec.channels() {
+        // alpha is ignored, as by the reference encoder
+        3 => for &[r, g, b, _] in pixels {
+            state.encode_some(&mut bytes, &[[r, g, b, 255]]).map_err(FileError::Encode)?;
+        }
+        _ => state.encode_some(&mut bytes, pixels).map_err(FileError::Encode)?,
+    }
     state.encode_flush(&mut bytes).map_err(FileError::Encode)?;
-    bytes.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 1]);
+    bytes.extend_from_slice(&PADDING);
     Ok(bytes)
 }
 
+/// Like [`encode_qoi_file`], but taking `header.spec.channels()` bytes per pixel.
+#[cfg(feature = "alloc")]
+pub fn encode_qoi_bytes(header: Header, bytes: &[u8]) -> Result<Vec<u8>, FileError> {
+    header.validate().map_err(FileError::Header)?;
+    check_length(header.pixel_count() * header.spec.channels() as u64, bytes.len())?;
+    let pixels = match header.spec.channels() {
+        3 => bytes.chunks_exact(3).map(|p| [p[0], p[1], p[2], 255]).collect::<Vec<_>>(),
+        _ => bytes.chunks_exact(4).map(|p| p.try_into().unwrap()).collect(),
+    };
+    encode_qoi_file(header, &pixels)
+}
+
+#[cfg(feature = "alloc")]
+fn check_length(expected: u64, got: usize) -> Result<(), FileError> {
+    let got = got as u64;
+    if got != expected {return Err(FileError::WrongLength{expected, got})}
+    Ok(())
+}
+
 #[cfg(test)]
 #[test]
 fn decode_qoifs() {
@@ -331,3 +685,77 @@ fn decode_qoifs() {
     assert_eq!(pixels[3], [27, 146, 55, 203]);
 }
 
+
+#[cfg(test)]
+#[test]
+fn encode_resumes() {
+    let pixels = (0 .. 500u32)
+        .map(|i| if i % 5 < 2 {[9, 9, 9, 255]} else {i.wrapping_mul(2654435761).to_le_bytes()})
+        .collect::<Vec<Pixel>>();
+
+    let mut expected = Vec::new();
+    let mut state = State::new();
+    state.encode_some(&mut expected, &pixels).unwrap();
+    state.encode_flush(&mut expected).unwrap();
+
+    let mut out = [0; 7];
+    assert!(State::new().encode_some(&mut &mut out[..], &pixels).is_err());
+
+    let mut bytes = Vec::new();
+    let mut state = State::new();
+    let mut pixels = &pixels[..];
+    while !pixels.is_empty() {
+        let progress = state.encode_partial(&mut out, pixels);
+        assert!(progress.pixels != 0 || progress.bytes == 0);
+        bytes.extend_from_slice(&out[..progress.bytes]);
+        pixels = &pixels[progress.pixels..];
+    }
+    let mut tail = &mut out[..];
+    state.encode_flush(&mut tail).unwrap();
+    let n = 7 - tail.len();
+    bytes.extend_from_slice(&out[..n]);
+    assert_eq!(bytes, expected);
+}
+
+#[cfg(test)]
+#[test]
+fn rgb() {
+    let header = Header{wide: 2, high: 1, spec: ColorSpec::Srgb8};
+    let qoif = encode_qoi_file(header, &[[1, 2, 3, 4], [1, 2, 3, 5]]).unwrap();
+    assert_eq!(qoif, encode_qoi_bytes(header, &[1, 2, 3, 1, 2, 3]).unwrap());
+
+    let (_, pixels) = decode_qoi_file(&qoif).unwrap();
+    assert_eq!(pixels, [[1, 2, 3, 255]; 2]);
+    let (header, bytes) = decode_qoi_bytes(&qoif).unwrap();
+    assert_eq!(header.spec.channels(), 3);
+    assert_eq!(bytes, [1, 2, 3, 1, 2, 3]);
+}
+
+#[cfg(test)]
+#[test]
+fn validation() {
+    let header = Header{wide: 2, high: 2, spec: ColorSpec::Srgb8A8};
+    assert!(matches!(
+        encode_qoi_file(header, &[[0; 4]; 3]),
+        Err(FileError::WrongLength{expected: 4, got: 3})
+    ));
+    assert!(matches!(
+        encode_qoi_file(Header{wide: 20_000, high: 20_000, ..header}, &[]),
+        Err(FileError::Header(HeaderError::TooLarge))
+    ));
+
+    let mut qoif = encode_qoi_file(header, &[[0; 4]; 4]).unwrap();
+    qoif.push(0);
+    assert!(matches!(decode_qoi_file(&qoif), Err(FileError::TrailingData(1))));
+    assert_eq!(decode_qoi_file(&qoif[..qoif.len() - 2]).unwrap_err().to_string(),
+        "missing or malformed end padding");
+}
+
+#[cfg(test)]
+#[test]
+fn hash_word_matches() {
+    for i in 0 .. 100_000u32 {
+        let pixel = i.wrapping_mul(2654435761).to_le_bytes();
+        assert_eq!(hash_word(u32::from_le_bytes(pixel)), hash_pixel(pixel) as usize);
+    }
+}
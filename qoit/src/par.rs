@@ -0,0 +1,39 @@
+//! Decoding independently coded streams, such as the images of an image set, across threads.
+
+use {
+    crate::{State, Pixel, DecodeError},
+    std::sync::Mutex,
+};
+
+/// Decodes each of `jobs`' input into its output, each from a fresh [`State`]. Jobs are handed
+/// out in order to as many threads as there are cores, so put the big ones first.
+pub fn decode_parallel(jobs: &mut [(&[u8], &mut [Pixel])]) -> Result<(), DecodeError> {
+    for_each(jobs, |input, output| State::new().decode_some(output, input).map(drop))
+}
+
+/// Runs `decode` over `jobs`, handing them out as [`decode_parallel`] does.
+pub(crate) fn for_each<F>(jobs: &mut [(&[u8], &mut [Pixel])], decode: F)
+    -> Result<(), DecodeError>
+where
+    F: Fn(&[u8], &mut [Pixel]) -> Result<(), DecodeError> + Sync,
+{
+    let n_threads = std::thread::available_parallelism()
+        .map_or(1, usize::from)
+        .min(jobs.len());
+    let queue = Mutex::new(jobs.iter_mut());
+
+    let work = || -> Result<(), DecodeError> {
+        loop {
+            let Some((input, output)) = queue.lock().unwrap().next() else {return Ok(())};
+            decode(input, output)?;
+        }
+    };
+
+    if n_threads <= 1 {return work()}
+    std::thread::scope(|scope| {
+        let threads = (0 .. n_threads)
+            .map(|_| scope.spawn(work))
+            .collect::<Vec<_>>();
+        threads.into_iter().try_for_each(|thread| thread.join().unwrap())
+    })
+}
@@ -0,0 +1,318 @@
+//! A variant for PlayStation colour, where every pixel is a 16-bit word of 5-bit red, green and
+//! blue and an STP bit. Ops work on the words, and pixels are expanded to [`Pixel`]s as they're
+//! decoded, so this is only lossless for pixels that came from such words in the first place.
+//!
+//! Words expand the way `formats` loads them: each channel to `c * 255 / 31`; `0x0000` to fully
+//! transparent; anything else with STP set to alpha 0x80, and opaque otherwise.
+//!
+//! | op    | bytes                 |                                                       |
+//! |-------|-----------------------|-------------------------------------------------------|
+//! | index | `00iiiiii`            |                                                       |
+//! | diff  | `01rrggbb`            | each channel's change, -2..=1, with STP kept          |
+//! | luma  | `10sggggg rrrrbbbb`   | green's change; red's and blue's, -8..=7, relative to |
+//! |       |                       | it; and whether STP flips                             |
+//! | run   | `11nnnnnn`            | 1..=63 repeats, so up to `0xfe`                       |
+//! | word  | `0xff`, then 2 bytes  | the word, little-endian                               |
+//!
+//! Channels wrap at 32. Streams start from transparent, with an index of all transparent.
+
+use crate::{Pixel, Progress, Put, DecodeError, EncodeError};
+
+const STP: u16 = 0x8000;
+
+/// Each channel's top bit
+const HIGH: u16 = 0x4210;
+/// Each channel's low four bits
+const LOW: u16 = 0x3def;
+
+static EXPAND: [u8; 32] = {
+    let mut table = [0; 32];
+    let mut c = 0;
+    while c < 32 {
+        table[c] = (c * 255 / 31) as u8;
+        c += 1;
+    }
+    table
+};
+
+/// Expands a word to a pixel.
+pub fn to_rgba(word: u16) -> Pixel {
+    if word == 0 {return [0; 4]}
+    let [r, g, b] = channels(word).map(|c| EXPAND[c as usize]);
+    [r, g, b, if word & STP != 0 {0x80} else {0xff}]
+}
+
+/// The word that expands to `pixel`, if there is one.
+pub fn to_word(pixel: Pixel) -> Option<u16> {
+    let [r, g, b, a] = pixel;
+    let stp = match a {
+        0x00 => return (pixel == [0; 4]).then_some(0),
+        0x80 => STP,
+        0xff => 0,
+        _    => return None,
+    };
+
+    let shrink = |v: u8| {
+        let c = (v as usize * 31).div_ceil(255);
+        (EXPAND[c] == v).then_some(c as u16)
+    };
+    let word = shrink(r)? | shrink(g)? << 5 | shrink(b)? << 10 | stp;
+    (word != 0).then_some(word)
+}
+
+fn channels(word: u16) -> [u16; 3] {
+    [word & 0x1f, word >> 5 & 0x1f, word >> 10 & 0x1f]
+}
+
+fn hash(word: u16) -> usize {
+    let [r, g, b] = channels(word);
+    (r * 3 + g * 5 + b * 7 + (word >> 15) * 11) as usize & 0x3f
+}
+
+/// Adds each channel of `b` to that of `a`, wrapping, and keeps `a`'s STP.
+fn add(a: u16, b: u16) -> u16 {
+    ((a & LOW) + (b & LOW)) ^ ((a ^ b) & HIGH) | a & STP
+}
+
+/// Packs per-channel changes, each taken mod 32.
+fn delta(dr: u8, dg: u8, db: u8) -> u16 {
+    (dr & 0x1f) as u16 | ((dg & 0x1f) as u16) << 5 | ((db & 0x1f) as u16) << 10
+}
+
+pub struct State {
+    prev: u16,
+    array: [u16; 64],
+    run: usize,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State {
+            prev: 0,
+            array: [0; 64],
+            run: 0,
+        }
+    }
+}
+
+impl State {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn decode_some<'b>(&mut self, output: &mut [Pixel], bytes: &'b [u8])
+        -> Result<&'b [u8], DecodeError>
+    {
+        let progress = self.decode_partial(output, bytes);
+        if progress.pixels < output.len() {return Err(DecodeError::Underrun)}
+        Ok(&bytes[progress.bytes..])
+    }
+
+    /// Decodes into `output` until it's full or `bytes` runs out, which may be partway through
+    /// an op; pass the rest of both again to carry on.
+    pub fn decode_partial(&mut self, output: &mut [Pixel], bytes: &[u8]) -> Progress {
+        let mut o = 0;
+        let mut i = 0;
+
+        while o < output.len() {
+            if self.run != 0 {
+                let n = self.run.min(output.len() - o);
+                output[o .. o + n].fill(to_rgba(self.prev));
+                o += n;
+                self.run -= n;
+                continue;
+            }
+
+            let (word, len) = match bytes[i..] {
+                [b0 @ 0x00 ..= 0x3f, ..] => (self.array[b0 as usize], 1),
+                [b0 @ 0x40 ..= 0x7f, ..] => {
+                    let d = |shift: u8| (b0 >> shift & 0x3).wrapping_sub(2);
+                    (add(self.prev, delta(d(4), d(2), d(0))), 1)
+                }
+                [b0 @ 0x80 ..= 0xbf, b1, ..] => {
+                    let dg = b0 & 0x1f;
+                    let dr = dg.wrapping_add(b1 >> 4).wrapping_sub(8);
+                    let db = dg.wrapping_add(b1 & 0xf).wrapping_sub(8);
+                    let flip = ((b0 >> 5 & 1) as u16) << 15;
+                    (add(self.prev, delta(dr, dg, db)) ^ flip, 2)
+                }
+                [b0 @ 0xc0 ..= 0xfe, ..] => {
+                    self.run = (b0 & 0x3f) as usize + 1;
+                    // as the encoder does, since the starting pixel may not be indexed yet
+                    self.array[hash(self.prev)] = self.prev;
+                    i += 1;
+                    continue;
+                }
+                [0xff, lo, hi, ..] => (u16::from_le_bytes([lo, hi]), 3),
+                _ => break,
+            };
+
+            output[o] = to_rgba(word);
+            o += 1;
+            i += len;
+            self.prev = word;
+            self.array[hash(word)] = word;
+        }
+
+        Progress{pixels: o, bytes: i}
+    }
+
+    /// Ends any pending run. On overrun the run is kept, so this can be tried again.
+    pub fn encode_flush(&mut self, output: &mut impl Put) -> Result<(), EncodeError> {
+        if self.run != 0 {
+            output.put(&[0xc0 | (self.run as u8 - 1)])?;
+        }
+        self.run = 0;
+        Ok(())
+    }
+
+    /// Fails with [`EncodeError::NotPsx`] at the first pixel without a word; see [`to_word`].
+    pub fn encode_some(&mut self, output: &mut impl Put, pixels: &[Pixel])
+        -> Result<(), EncodeError>
+    {
+        for &pixel in pixels {
+            let word = to_word(pixel).ok_or(EncodeError::NotPsx)?;
+            self.encode_word(output, word)?;
+        }
+        Ok(())
+    }
+
+    /// Encodes one word, leaving the state as it was if `output` can't take the result.
+    fn encode_word(&mut self, output: &mut impl Put, word: u16) -> Result<(), EncodeError> {
+        let hash = hash(word);
+
+        if word == self.prev {
+            if self.run == 62 {
+                output.put(&[0xc0 | 62])?;
+                self.run = 0;
+            }
+            else {
+                self.run += 1;
+            }
+        }
+        else {
+            // room for the end of a run, then the longest op
+            let mut ops = [0; 4];
+            let mut n = 0;
+            let mut op = |bytes: &[u8]| {
+                ops[n .. n + bytes.len()].copy_from_slice(bytes);
+                n += bytes.len();
+            };
+
+            if self.run != 0 {
+                op(&[0xc0 | (self.run as u8 - 1)]);
+            }
+
+            let [r, g, b] = channels(word).map(|c| c as u8);
+            let [pr, pg, pb] = channels(self.prev).map(|c| c as u8);
+            let dr = r.wrapping_sub(pr) & 0x1f;
+            let dg = g.wrapping_sub(pg) & 0x1f;
+            let db = b.wrapping_sub(pb) & 0x1f;
+            let flip = (word ^ self.prev) & STP != 0;
+
+            let dr2 = (dr + 2) & 0x1f;
+            let dg2 = (dg + 2) & 0x1f;
+            let db2 = (db + 2) & 0x1f;
+
+            let drdg8 = dr.wrapping_sub(dg).wrapping_add(8) & 0x1f;
+            let dbdg8 = db.wrapping_sub(dg).wrapping_add(8) & 0x1f;
+
+            if word == self.array[hash] {
+                op(&[hash as u8]);
+            }
+            else if !flip && dr2 < 4 && dg2 < 4 && db2 < 4 {
+                op(&[0x40 | dr2 << 4 | dg2 << 2 | db2]);
+            }
+            else if drdg8 < 16 && dbdg8 < 16 {
+                op(&[0x80 | (flip as u8) << 5 | dg, drdg8 << 4 | dbdg8]);
+            }
+            else {
+                let [lo, hi] = word.to_le_bytes();
+                op(&[0xff, lo, hi]);
+            }
+
+            output.put(&ops[..n])?;
+            self.run = 0;
+            self.prev = word;
+        }
+
+        self.array[hash] = word;
+        Ok(())
+    }
+}
+
+/// Decodes each of `jobs`' input into its output, each from a fresh [`State`], across threads
+/// as [`decode_parallel`](crate::decode_parallel) does.
+#[cfg(feature = "std")]
+pub fn decode_parallel(jobs: &mut [(&[u8], &mut [Pixel])]) -> Result<(), DecodeError> {
+    crate::par::for_each(jobs, |input, output| State::new().decode_some(output, input).map(drop))
+}
+
+#[cfg(test)]
+#[test]
+fn words() {
+    for word in 0 ..= u16::MAX {
+        assert_eq!(to_word(to_rgba(word)), Some(word), "{word:04x}");
+    }
+    assert_eq!(to_word([0, 0, 0, 0xff]), None);
+    assert_eq!(to_word([1, 0, 0, 0xff]), None);
+    assert_eq!(to_word([8, 0, 0, 0x7f]), None);
+}
+
+#[cfg(all(test, feature = "alloc"))]
+#[test]
+fn round_trip() {
+    use alloc::vec::Vec;
+
+    let mut x = 1u32;
+    let mut next = move || {x ^= x << 13; x ^= x >> 17; x ^= x << 5; x};
+    let mut word = 0u16;
+    let words = (0 .. 20_000)
+        .map(|_| {
+            let r = next();
+            // mostly small steps and repeats, as in real textures
+            word = match r % 8 {
+                0     => r as u16 >> 8,
+                1 | 2 => word,
+                3     => word ^ STP,
+                _     => {
+                    let d = |shift: u32, mask: u32, bias: u8| ((r >> shift & mask) as u8).wrapping_sub(bias);
+                    add(word, delta(d(3, 0x3, 2), d(5, 0xf, 8), d(9, 0x3, 2)))
+                }
+            };
+            word
+        })
+        .collect::<Vec<_>>();
+    let pixels = words.iter().copied().map(to_rgba).collect::<Vec<_>>();
+
+    let mut bytes = Vec::new();
+    let mut state = State::new();
+    state.encode_some(&mut bytes, &pixels).unwrap();
+    state.encode_flush(&mut bytes).unwrap();
+
+    let mut decoded = alloc::vec![[0; 4]; pixels.len()];
+    let rest = State::new().decode_some(&mut decoded, &bytes).unwrap();
+    assert!(rest.is_empty());
+    assert_eq!(decoded, pixels);
+    // back to the very same words, STP and all
+    assert!(words.iter().any(|&word| word & STP != 0 && word != STP));
+    let again = decoded.iter().map(|&pixel| to_word(pixel).unwrap()).collect::<Vec<_>>();
+    assert_eq!(again, words);
+
+    // a few bytes at a time, as from a stream, often ending partway through an op
+    let mut state = State::new();
+    let (mut o, mut i) = (0, 0);
+    while o < decoded.len() {
+        let end = bytes.len().min(i + 3);
+        let progress = state.decode_partial(&mut decoded[o..], &bytes[i..end]);
+        o += progress.pixels;
+        i += progress.bytes;
+    }
+    assert_eq!(decoded, pixels);
+
+    let mut state = State::new();
+    assert!(matches!(
+        state.encode_some(&mut Vec::new(), &[[0, 0, 0, 0xff]]),
+        Err(EncodeError::NotPsx),
+    ));
+}
@@ -0,0 +1,126 @@
+//! Checks against the reference images in `test-imgs`, each a `.png` with the `.qoi` the
+//! reference encoder made from it, and round trips of random images.
+
+use {
+    qoit::{ColorSpec, Header, Pixel},
+    proptest::prelude::*,
+    std::path::{Path, PathBuf},
+};
+
+fn test_imgs() -> Vec<PathBuf> {
+    let dir = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/test-imgs"));
+    let mut stems = std::fs::read_dir(dir).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "qoi"))
+        .map(|path| path.with_extension(""))
+        .collect::<Vec<_>>();
+    stems.sort();
+    assert!(!stems.is_empty());
+    stems
+}
+
+/// The `.png`'s pixels, with as many channels as it has
+fn load_png(stem: &Path) -> (u32, u32, ColorSpec, Vec<u8>) {
+    let image = image::open(stem.with_extension("png")).unwrap();
+    let (wide, high) = (image.width(), image.height());
+    if image.color().has_alpha() {(wide, high, ColorSpec::Srgb8A8, image.into_rgba8().into_raw())}
+    else                         {(wide, high, ColorSpec::Srgb8,   image.into_rgb8().into_raw())}
+}
+
+#[test]
+fn decode_matches_png() {
+    for stem in test_imgs() {
+        let qoif = std::fs::read(stem.with_extension("qoi")).unwrap();
+        let (header, bytes) = qoit::decode_qoi_bytes(&qoif).unwrap();
+        let (wide, high, spec, png) = load_png(&stem);
+        assert_eq!((header.wide, header.high, header.spec), (wide, high, spec), "{stem:?}");
+        assert!(bytes == png, "{stem:?}: pixels differ");
+    }
+}
+
+#[test]
+fn encode_matches_reference() {
+    for stem in test_imgs() {
+        let (wide, high, spec, png) = load_png(&stem);
+        let qoif = qoit::encode_qoi_bytes(Header{wide, high, spec}, &png).unwrap();
+        let reference = std::fs::read(stem.with_extension("qoi")).unwrap();
+        assert!(qoif == reference, "{stem:?}: encoding differs");
+    }
+}
+
+#[test]
+fn parallel_matches_serial() {
+    let files = test_imgs().iter()
+        .map(|stem| std::fs::read(stem.with_extension("qoi")).unwrap())
+        .collect::<Vec<_>>();
+    let mut outputs = files.iter()
+        .map(|qoif| {
+            let header = Header::from_bytes(qoif[..14].try_into().unwrap()).unwrap();
+            vec![[0; 4]; header.pixel_count() as usize]
+        })
+        .collect::<Vec<_>>();
+    let mut jobs = files.iter().zip(&mut outputs)
+        .map(|(qoif, output)| (&qoif[14..], &mut output[..]))
+        .collect::<Vec<_>>();
+    qoit::decode_parallel(&mut jobs).unwrap();
+
+    for (qoif, output) in files.iter().zip(outputs) {
+        assert!(qoit::decode_qoi_file(qoif).unwrap().1 == output);
+    }
+}
+
+/// Pixels drawn mostly from a few colours near each other, so that every op gets used.
+fn image() -> impl Strategy<Value = (Header, Vec<Pixel>)> {
+    let spec = prop_oneof![Just(ColorSpec::Srgb8), Just(ColorSpec::Srgb8A8)];
+    (1 .. 40u32, 1 .. 40u32, spec).prop_flat_map(|(wide, high, spec)| {
+        let pixel = prop_oneof![
+            4 => (0 .. 4u8, 250 .. 255u8).prop_map(|(v, a)| [v * 3, v * 5, 100 - v, a]),
+            1 => any::<Pixel>(),
+        ];
+        let pixels = prop::collection::vec(pixel, (wide * high) as usize);
+        pixels.prop_map(move |mut pixels| {
+            if spec.channels() == 3 {
+                for pixel in &mut pixels { pixel[3] = 255; }
+            }
+            (Header{wide, high, spec}, pixels)
+        })
+    })
+}
+
+proptest! {
+    #[test]
+    fn decoders_agree(bytes in prop::collection::vec(any::<u8>(), 0 .. 400), n in 0 .. 300usize) {
+        let mut output = vec![[0; 4]; n];
+        let progress = qoit::State::new().decode_partial(&mut output, &bytes);
+        let mut expected = vec![[0; 4]; n];
+        let expected_progress = qoit::State::new().decode_partial_reference(&mut expected, &bytes);
+        prop_assert_eq!(progress, expected_progress);
+        prop_assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn round_trip((header, pixels) in image()) {
+        let qoif = qoit::encode_qoi_file(header, &pixels).unwrap();
+        let (decoded_header, decoded) = qoit::decode_qoi_file(&qoif).unwrap();
+        prop_assert_eq!(decoded_header.spec, header.spec);
+        prop_assert_eq!(decoded, pixels);
+    }
+
+    #[test]
+    fn streamed_round_trip((header, pixels) in image(), row in 1 .. 50usize) {
+        let mut writer = qoit::QoiWriter::new(Vec::new(), header).unwrap();
+        for chunk in pixels.chunks(row) {
+            writer.write_pixels(chunk).unwrap();
+        }
+        let qoif = writer.finish().unwrap();
+        prop_assert_eq!(&qoif, &qoit::encode_qoi_file(header, &pixels).unwrap());
+
+        let mut reader = qoit::QoiReader::new(&qoif[..]).unwrap();
+        let mut decoded = vec![[0; 4]; pixels.len()];
+        for chunk in decoded.chunks_mut(row) {
+            reader.read_pixels(chunk).unwrap();
+        }
+        reader.finish().unwrap();
+        prop_assert_eq!(decoded, pixels);
+    }
+}
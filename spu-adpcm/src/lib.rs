@@ -1,14 +1,110 @@
-pub fn add(left: usize, right: usize) -> usize {
-    left + right
+pub mod vab;
+
+pub use vab::Vab;
+
+/// Samples per 16-byte ADPCM frame.
+pub const FRAME_SAMPLES: usize = 28;
+
+const FLAG_END: u8 = 1;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("adpcm data is not a whole number of frames ({0}B)")]
+    Ragged(usize),
+
+    #[error("bad filter {filter} in adpcm frame at +{offset:#x}")]
+    BadFilter{offset: usize, filter: u8},
+}
+
+/// Splits a run of ADPCM frames after each frame carrying the end flag.
+pub fn split_samples(adpcm: &[u8]) -> Result<impl Iterator<Item = &[u8]>, Error> {
+    let frames = frames(adpcm)?;
+    let chunks = frames
+        .split_inclusive(|frame| frame[1] & FLAG_END != 0)
+        .map(bytemuck::cast_slice);
+    Ok(chunks)
+}
+
+/// Decodes a whole sample's worth of ADPCM frames to 16-bit PCM.
+pub fn decode(adpcm: &[u8]) -> Result<Vec<i16>, Error> {
+    let frames = frames(adpcm)?;
+    let mut dec = Decoder::default();
+    let mut pcm = Vec::with_capacity(frames.len() * FRAME_SAMPLES);
+    for (i, frame) in frames.iter().enumerate() {
+        let samples = dec.frame(frame)
+            .ok_or(Error::BadFilter{offset: i * 16, filter: (frame[0] >> 4) & 7})?;
+        pcm.extend(samples);
+    }
+    Ok(pcm)
+}
+
+fn frames(adpcm: &[u8]) -> Result<&[[u8; 16]], Error> {
+    bytemuck::try_cast_slice(adpcm).map_err(|_| Error::Ragged(adpcm.len()))
+}
+
+/// ADPCM decoder state; the two previous output samples.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Decoder {
+    p: i32,
+    pp: i32,
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+impl Decoder {
+    /// Decodes one frame, or `None` if its filter is out of range.
+    pub fn frame(&mut self, &[sf, _flags, ref pairs@..]: &[u8; 16]) -> Option<[i16; FRAME_SAMPLES]> {
+        let shift  = sf & 0xf;
+        let filter = (sf >> 4) & 0x7;
+        const PT: [i32; 5] = [0, 60, 115,  98, 122];
+        const NT: [i32; 5] = [0,  0, -52, -55, -60];
+        let f0 = *PT.get(filter as usize)?;
+        let f1 = NT[filter as usize];
 
-    #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
+        let mut samples = [0; FRAME_SAMPLES];
+        for (i, nybble) in pairs.iter().flat_map(|pair| [pair & 0xf, pair >> 4]).enumerate() {
+            let shifted = (((nybble as i16) << 12) >> shift) as i32;
+            let sample = shifted + ((self.p * f0 + self.pp * f1 + 32) >> 6);
+            let sample = sample.clamp(-0x8000, 0x7fff);
+            self.pp = self.p;
+            self.p = sample;
+            samples[i] = sample as i16;
+        }
+
+        Some(samples)
     }
 }
+
+/// Writes mono 16-bit PCM as a RIFF WAVE file.
+pub fn write_wav(mut out: impl std::io::Write, rate: u32, pcm: &[i16]) -> std::io::Result<()> {
+    let pcm: Vec<u8> = pcm.iter().copied().flat_map(i16::to_le_bytes).collect();
+
+    let fmt_chunk = [
+        u32::from_le_bytes(*b"fmt "),
+        16,
+        0x0001_0001,
+        rate,
+        rate * 2,
+        0x0010_0002,
+    ];
+
+    let data_head = [
+        u32::from_le_bytes(*b"data"),
+        pcm.len() as u32
+    ];
+
+    let contents_len
+        = 4
+        + std::mem::size_of_val(&fmt_chunk) as u32
+        + std::mem::size_of_val(&data_head) as u32
+        + pcm.len() as u32;
+
+    let riff_head = [
+        u32::from_le_bytes(*b"RIFF"),
+        contents_len,
+        u32::from_le_bytes(*b"WAVE"),
+    ];
+
+    out.write_all(bytemuck::bytes_of(&riff_head))?;
+    out.write_all(bytemuck::bytes_of(&fmt_chunk))?;
+    out.write_all(bytemuck::bytes_of(&data_head))?;
+    out.write_all(&pcm[..])
+}
@@ -0,0 +1,365 @@
+//! VAB sound banks, as a `.vh` header paired with a `.vb` body of ADPCM samples.
+//!
+//! The header is little-endian:
+//!
+//! | offset | size       | contents                          |
+//! |--------|------------|-----------------------------------|
+//! | 0      | 32         | bank header, magic `pBAV`         |
+//! | 32     | 128 × 16   | program attributes                |
+//! | 2080   | ps×16 × 32 | tone attributes, 16 per program   |
+//! | ...    | 256 × 2    | sample sizes in 8-byte units      |
+//!
+//! Samples sit back to back in the body, numbered from 1 in the order of the size table.
+
+use {
+    bytemuck::{self as bm, AnyBitPattern, Pod, Zeroable},
+    std::ops::Range,
+};
+
+/// The SPU's reference rate; a tone plays at this rate when keyed at its centre note.
+pub const BASE_RATE: f32 = 44_100.;
+
+/// The note sound effects are conventionally keyed at.
+pub const REFERENCE_NOTE: u8 = 60;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("not a vab header (magic {0:02x?})")]
+    BadMagic([u8; 4]),
+
+    #[error("vab header truncated: {part} needs {need}B at +{offset:#x}, only {have}B left")]
+    Truncated{part: &'static str, offset: usize, need: usize, have: usize},
+
+    #[error("vab header lists {listed} programs but {used} have tones")]
+    ProgramCount{listed: usize, used: usize},
+
+    #[error("program {program} tone {tone} refers to missing sample {vag}")]
+    MissingSample{program: usize, tone: usize, vag: u16},
+}
+
+#[derive(Debug, Clone)]
+pub struct Vab {
+    pub id:      u32,
+    pub version: u32,
+    pub volume:  u8,
+    pub pan:     u8,
+    /// Program number and attributes, for programs with any tones.
+    pub programs: Vec<(u8, Program)>,
+    /// Byte ranges of each sample in the body; sample `n` is at `n - 1`.
+    pub samples: Vec<Range<usize>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Program {
+    pub volume:   u8,
+    pub pan:      u8,
+    pub priority: u8,
+    pub mode:     u8,
+    pub tones:    Vec<Tone>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Tone {
+    pub priority: u8,
+    pub mode:     u8,
+    pub volume:   u8,
+    pub pan:      u8,
+    /// Note at which the sample plays at [`BASE_RATE`].
+    pub center:   u8,
+    /// Fine tuning, in 1/128ths of a semitone.
+    pub shift:    u8,
+    /// Range of notes this tone answers to.
+    pub notes:    [u8; 2],
+    pub vibrato:  [u8; 2],
+    pub portamento: [u8; 2],
+    /// Pitch-bend range down and up, in semitones.
+    pub bend:     [u8; 2],
+    pub adsr:     Adsr,
+    /// Sample number, counting from 1.
+    pub vag:      u16,
+}
+
+impl Tone {
+    /// Playback rate of the sample when keyed at `note`.
+    pub fn rate_at(&self, note: u8) -> f32 {
+        let semis = note as f32 - self.center as f32 + self.shift as f32 / 128.;
+        BASE_RATE * (semis / 12.).exp2()
+    }
+
+    /// Playback rate at [`REFERENCE_NOTE`]; the rate to write the decoded sample out at.
+    pub fn sample_rate(&self) -> u32 {
+        self.rate_at(REFERENCE_NOTE).round() as u32
+    }
+}
+
+/// Envelope settings, unpacked from the SPU's ADSR register pair.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Adsr {
+    pub attack:  Slope,
+    /// Decay is always exponential and decreasing.
+    pub decay_rate: u8,
+    /// Level at which decay hands over to sustain, out of 0x8000.
+    pub sustain_level: u16,
+    pub sustain: Slope,
+    pub sustain_decreasing: bool,
+    pub release: Slope,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Slope {
+    pub rate: u8,
+    pub exponential: bool,
+}
+
+impl Adsr {
+    pub fn from_regs(adsr1: u16, adsr2: u16) -> Self {
+        let bit = |r: u16, i: u32| r >> i & 1 != 0;
+        let field = |r: u16, lo: u32, bits: u32| (r >> lo & ((1 << bits) - 1)) as u8;
+        Adsr {
+            attack: Slope{rate: field(adsr1, 8, 7), exponential: bit(adsr1, 15)},
+            decay_rate: field(adsr1, 4, 4),
+            sustain_level: (field(adsr1, 0, 4) as u16 + 1) * 0x800,
+            sustain: Slope{rate: field(adsr2, 6, 7), exponential: bit(adsr2, 15)},
+            sustain_decreasing: bit(adsr2, 14),
+            release: Slope{rate: field(adsr2, 0, 5), exponential: bit(adsr2, 5)},
+        }
+    }
+}
+
+impl Vab {
+    pub fn parse(vh: &[u8]) -> Result<Self, Error> {
+        let mut cursor = Cursor{bs: vh, offset: 0};
+
+        let head: &RawHeader = cursor.grab("bank header")?;
+        if &head.magic != b"pBAV" { return Err(Error::BadMagic(head.magic)); }
+
+        let raw_progs: &[RawProgram] = cursor.grab_n("program table", 128)?;
+        let n_progs = u16::from_le_bytes(head.programs) as usize;
+        let raw_tones: &[[RawTone; 16]] = cursor.grab_n("tone table", n_progs)?;
+        let raw_sizes: &[[u8; 2]] = cursor.grab_n("sample size table", 256)?;
+
+        let mut start = 0;
+        let n_vags = u16::from_le_bytes(head.vags) as usize;
+        let samples = raw_sizes[1 ..= n_vags.min(255)].iter()
+            .map(|&size| {
+                let len = u16::from_le_bytes(size) as usize * 8;
+                start += len;
+                start - len .. start
+            })
+            .collect::<Vec<_>>();
+
+        let used = raw_progs.iter().enumerate()
+            .filter(|(_, p)| p.tones != 0)
+            .collect::<Vec<_>>();
+        if used.len() != n_progs {
+            return Err(Error::ProgramCount{listed: n_progs, used: used.len()});
+        }
+
+        let programs = used.into_iter().zip(raw_tones)
+            .map(|((number, prog), tones)| {
+                let tones = tones[.. (prog.tones as usize).min(16)].iter().enumerate()
+                    .map(|(i, raw)| {
+                        let vag = u16::from_le_bytes(raw.vag);
+                        if vag == 0 || vag as usize > samples.len() {
+                            return Err(Error::MissingSample{program: number, tone: i, vag});
+                        }
+                        Ok(Tone {
+                            priority: raw.prior,
+                            mode:     raw.mode,
+                            volume:   raw.vol,
+                            pan:      raw.pan,
+                            center:   raw.center,
+                            shift:    raw.shift,
+                            notes:    [raw.min, raw.max],
+                            vibrato:  [raw.vib_w, raw.vib_t],
+                            portamento: [raw.por_w, raw.por_t],
+                            bend:     [raw.pb_min, raw.pb_max],
+                            adsr:     Adsr::from_regs(
+                                u16::from_le_bytes(raw.adsr1),
+                                u16::from_le_bytes(raw.adsr2),
+                            ),
+                            vag,
+                        })
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                let prog = Program {
+                    volume:   prog.mvol,
+                    pan:      prog.mpan,
+                    priority: prog.prior,
+                    mode:     prog.mode,
+                    tones,
+                };
+                Ok((number as u8, prog))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Vab {
+            id:      u32::from_le_bytes(head.id),
+            version: u32::from_le_bytes(head.version),
+            volume:  head.mvol,
+            pan:     head.pan,
+            programs,
+            samples,
+        })
+    }
+
+    /// The ADPCM data for sample `vag` within the body, if the body is long enough.
+    pub fn sample<'vb>(&self, vb: &'vb [u8], vag: u16) -> Option<&'vb [u8]> {
+        let range = self.samples.get((vag as usize).checked_sub(1)?)?;
+        vb.get(range.clone())
+    }
+
+    pub fn tones(&self) -> impl Iterator<Item = (u8, &Tone)> {
+        self.programs.iter()
+            .flat_map(|(i, prog)| prog.tones.iter().map(move |tone| (*i, tone)))
+    }
+}
+
+/// Casts the header's little-endian records in place. This can't share `formats::reader`, since
+/// `formats` depends on this crate to identify sound banks.
+struct Cursor<'a> {
+    bs: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn grab<T: AnyBitPattern>(&mut self, part: &'static str) -> Result<&'a T, Error> {
+        Ok(&self.grab_n(part, 1)?[0])
+    }
+
+    fn grab_n<T: AnyBitPattern>(&mut self, part: &'static str, n: usize)
+        -> Result<&'a [T], Error>
+    {
+        let need = std::mem::size_of::<T>() * n;
+        if self.bs.len() < need {
+            let have = self.bs.len();
+            return Err(Error::Truncated{part, offset: self.offset, need, have});
+        }
+        let (bytes, rest) = self.bs.split_at(need);
+        self.bs = rest;
+        self.offset += need;
+        Ok(bm::cast_slice(bytes))
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct RawHeader {
+    magic:     [u8; 4],     //  4
+    version:   [u8; 4],     //  8
+    id:        [u8; 4],     // 12
+    _size:     [u8; 4],     // 16
+    _reserved0: [u8; 2],    // 18
+    programs:  [u8; 2],     // 20
+    _tones:    [u8; 2],     // 22
+    vags:      [u8; 2],     // 24
+    mvol:      u8,
+    pan:       u8,
+    _attr:     [u8; 2],     // 28
+    _reserved1: [u8; 4],    // 32
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct RawProgram {
+    tones:     u8,
+    mvol:      u8,
+    prior:     u8,
+    mode:      u8,
+    mpan:      u8,
+    _reserved0: u8,
+    _attr:     [u8; 2],     //  8
+    _reserved1: [u8; 8],    // 16
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct RawTone {
+    prior:     u8,
+    mode:      u8,
+    vol:       u8,
+    pan:       u8,
+    center:    u8,
+    shift:     u8,
+    min:       u8,
+    max:       u8,          //  8
+    vib_w:     u8,
+    vib_t:     u8,
+    por_w:     u8,
+    por_t:     u8,
+    pb_min:    u8,
+    pb_max:    u8,
+    _reserved0: [u8; 2],    // 16
+    adsr1:     [u8; 2],
+    adsr2:     [u8; 2],
+    _prog:     [u8; 2],
+    vag:       [u8; 2],     // 24
+    _reserved1: [u8; 8],    // 32
+}
+
+#[allow(dead_code)]
+static CHECK_HEADER_SIZE: [(); 32-std::mem::size_of::<RawHeader>()] = [];
+#[allow(dead_code)]
+static CHECK_PROGRAM_SIZE: [(); 16-std::mem::size_of::<RawProgram>()] = [];
+#[allow(dead_code)]
+static CHECK_TONE_SIZE: [(); 32-std::mem::size_of::<RawTone>()] = [];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bank() -> Vec<u8> {
+        let mut vh = vec![0; 32 + 128*16 + 16*32 + 512];
+        vh[0..4].copy_from_slice(b"pBAV");
+        vh[18..20].copy_from_slice(&1u16.to_le_bytes());
+        vh[22..24].copy_from_slice(&2u16.to_le_bytes());
+
+        // program 3 has one tone
+        vh[32 + 3*16] = 1;
+
+        let tone = &mut vh[32 + 128*16 ..][..32];
+        tone[4] = 72;
+        tone[5] = 64;
+        tone[16..18].copy_from_slice(&0x80ffu16.to_le_bytes());
+        tone[18..20].copy_from_slice(&0x5fc0u16.to_le_bytes());
+        tone[22..24].copy_from_slice(&2u16.to_le_bytes());
+
+        let sizes = 32 + 128*16 + 16*32;
+        vh[sizes+2 .. sizes+4].copy_from_slice(&4u16.to_le_bytes());
+        vh[sizes+4 .. sizes+6].copy_from_slice(&6u16.to_le_bytes());
+        vh
+    }
+
+    #[test]
+    fn parse() {
+        let vab = Vab::parse(&bank()).unwrap();
+        assert_eq!(vab.samples, [0..32, 32..80]);
+        assert_eq!(vab.programs.len(), 1);
+
+        let (number, prog) = &vab.programs[0];
+        assert_eq!(*number, 3);
+        let tone = prog.tones[0];
+        assert_eq!(tone.vag, 2);
+        assert_eq!(tone.sample_rate(), 22_696);
+        assert_eq!(tone.adsr.attack, Slope{rate: 0, exponential: true});
+        assert_eq!(tone.adsr.decay_rate, 0xf);
+        assert_eq!(tone.adsr.sustain_level, 0x8000);
+        assert_eq!(tone.adsr.sustain, Slope{rate: 0x7f, exponential: false});
+        assert!(tone.adsr.sustain_decreasing);
+        assert_eq!(tone.adsr.release, Slope{rate: 0, exponential: false});
+
+        let vb = [0u8; 80];
+        assert_eq!(vab.sample(&vb, 2).map(<[u8]>::len), Some(48));
+        assert_eq!(vab.sample(&vb, 0), None);
+    }
+
+    #[test]
+    fn truncated() {
+        let vh = bank();
+        assert!(matches!(
+            Vab::parse(&vh[..vh.len() - 1]),
+            Err(Error::Truncated{part: "sample size table", ..})
+        ));
+    }
+}